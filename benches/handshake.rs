@@ -0,0 +1,191 @@
+//! Handshake benchmarks.
+//!
+//! Each benchmark runs on a single thread, so the throughput criterion reports
+//! for the `handshake` group (in elements per second) is handshakes per second per core.
+//!
+//! ```sh
+//! cargo bench --features testing
+//! ```
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use ssb_crypto::ephemeral::generate_ephemeral_keypair;
+use ssb_handshake::testing::*;
+use ssb_handshake::*;
+
+use async_ringbuffer::Duplex;
+use futures::executor::block_on;
+use futures::future::join;
+
+/// Both sides of a handshake, in memory.
+struct Pair<'a> {
+    client: ClientHandshake<'a>,
+    server: ServerHandshake<'a>,
+    buf: [u8; MAX_MESSAGE_SIZE],
+    len: usize,
+    sender: Role,
+}
+
+impl<'a> Pair<'a> {
+    fn new(client: ClientHandshake<'a>, server: ServerHandshake<'a>) -> Pair<'a> {
+        Pair {
+            client,
+            server,
+            buf: [0; MAX_MESSAGE_SIZE],
+            len: 0,
+            sender: Role::Client,
+        }
+    }
+
+    /// Have the sender of the current message write it into the buffer.
+    fn write(&mut self) {
+        let stage = self.client.stage().unwrap();
+        let buf = &mut self.buf[..stage.message_size()];
+        self.sender = stage.sender();
+        self.len = match self.sender {
+            Role::Client => self.client.write_message(buf),
+            Role::Server => self.server.write_message(buf),
+        };
+    }
+
+    /// Have the receiver of the current message read it from the buffer.
+    fn read(&mut self) {
+        let msg = &self.buf[..self.len];
+        match self.sender {
+            Role::Client => self.server.read_message(msg),
+            Role::Server => self.client.read_message(msg),
+        }
+        .unwrap();
+    }
+
+    /// Exchange messages until `stage` is the next one to be sent.
+    fn advance_to(mut self, stage: Stage) -> Pair<'a> {
+        while self.client.stage() != Some(stage) {
+            self.write();
+            self.read();
+        }
+        self
+    }
+
+    /// Exchange the remaining messages.
+    fn finish(mut self) -> HandshakeKeys {
+        while self.client.stage().is_some() {
+            self.write();
+            self.read();
+        }
+        self.server.into_keys().unwrap();
+        self.client.into_keys().unwrap()
+    }
+}
+
+/// Writing (`new`) and reading (`verify`) each of the four messages.
+fn bench_messages(c: &mut Criterion) {
+    let keys = Keys::new();
+    let pair = || {
+        Pair::new(
+            keys.client(client_eph_keypair()),
+            keys.server(server_eph_keypair()),
+        )
+    };
+
+    let mut group = c.benchmark_group("messages");
+    for &stage in Stage::ALL.iter() {
+        group.bench_function(format!("{:?}/new", stage), |b| {
+            b.iter_batched(
+                || pair().advance_to(stage),
+                |mut p| {
+                    p.write();
+                    p
+                },
+                BatchSize::SmallInput,
+            )
+        });
+        group.bench_function(format!("{:?}/verify", stage), |b| {
+            b.iter_batched(
+                || {
+                    let mut p = pair().advance_to(stage);
+                    p.write();
+                    p
+                },
+                |mut p| {
+                    p.read();
+                    p
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+/// Complete handshakes, including generating the ephemeral keys.
+fn bench_handshakes(c: &mut Criterion) {
+    let keys = Keys::new();
+    let server_config = ServerConfig::new(keys.net_key.clone(), server_keypair()).unwrap();
+    let client_config =
+        ClientConfig::new(keys.net_key.clone(), client_keypair(), keys.server.public).unwrap();
+    let Exchange { c2s, s2c, .. } = keys.exchange();
+
+    let mut group = c.benchmark_group("handshake");
+    group.throughput(Throughput::Elements(1));
+
+    // Both sides, in memory.
+    group.bench_function("sans_io", |b| {
+        b.iter(|| {
+            Pair::new(
+                keys.client(generate_ephemeral_keypair()),
+                keys.server(generate_ephemeral_keypair()),
+            )
+            .finish()
+        })
+    });
+    group.bench_function("sans_io_with_config", |b| {
+        b.iter(|| {
+            Pair::new(
+                ClientHandshake::with_config(&client_config, generate_ephemeral_keypair()),
+                ServerHandshake::with_config(&server_config, generate_ephemeral_keypair()),
+            )
+            .finish()
+        })
+    });
+
+    // One side, replaying the other side's recorded messages.
+    group.bench_function("sync_client", |b| {
+        b.iter(|| {
+            let stream = ReplayStream::new(&s2c);
+            sync::client_side(
+                stream,
+                &keys.net_key,
+                &keys.client,
+                &keys.server.public,
+                client_eph_keypair(),
+            )
+            .unwrap()
+        })
+    });
+    group.bench_function("sync_server", |b| {
+        b.iter(|| {
+            let stream = ReplayStream::new(&c2s);
+            sync::server_side(stream, &keys.net_key, &keys.server, server_eph_keypair()).unwrap()
+        })
+    });
+
+    // Both sides, over an in-memory async stream.
+    group.bench_function("async_duplex", |b| {
+        b.iter(|| {
+            let (mut c_stream, mut s_stream) = Duplex::pair(1024);
+            let client = client_side(
+                &mut c_stream,
+                &keys.net_key,
+                &keys.client,
+                &keys.server.public,
+            );
+            let server = server_side(&mut s_stream, &keys.net_key, &keys.server);
+            let (c, s) = block_on(join(client, server));
+            (c.unwrap(), s.unwrap())
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_messages, bench_handshakes);
+criterion_main!(benches);
@@ -0,0 +1,43 @@
+use async_ringbuffer::Duplex;
+use futures::executor::block_on;
+use futures::future::join;
+use ssb_crypto::ephemeral::{generate_ephemeral_keypair, EphPublicKey, EphSecretKey};
+use ssb_crypto::{Keypair, NetworkKey};
+use ssb_handshake::transcript::{replay_server, Transcript, TranscriptRecorder};
+use ssb_handshake::*;
+
+// Records the server side of a handshake with a client that uses the wrong
+// server public key, prints the transcript, and replays it to find out which
+// check failed.
+//
+// Replaying needs the server's ephemeral secret key, which gives up forward
+// secrecy for the recorded connection; only keep it for debugging.
+fn main() {
+    let (mut c_stream, s_stream) = Duplex::pair(1024);
+    let skey = Keypair::generate();
+    let ckey = Keypair::generate();
+    let wrong_pk = Keypair::generate().public;
+    let net_key = NetworkKey::SSB_MAIN_NET;
+
+    // The replay needs the same ephemeral keypair, so keep a copy of it.
+    let s_eph = generate_ephemeral_keypair();
+    let replay_eph = (EphPublicKey(s_eph.0 .0), EphSecretKey(s_eph.1 .0));
+
+    let mut recorder = TranscriptRecorder::new(s_stream, Role::Server);
+    let client = client_side(&mut c_stream, &net_key, &ckey, &wrong_pk);
+    let server = server_side_with(
+        &mut recorder,
+        ServerOptions::new(&net_key, &skey).eph_keypair(s_eph),
+    );
+    let (_, s_out) = block_on(join(client, server));
+    println!("handshake result: {:?}", s_out.map(|_| ()));
+
+    let text = recorder.transcript().to_string();
+    println!("{}", text);
+
+    let transcript: Transcript = text.parse().unwrap();
+    match replay_server(&transcript, &net_key, &skey, replay_eph) {
+        Ok(_) => println!("replay succeeded"),
+        Err(e) => println!("replay failed at {}", e),
+    }
+}
@@ -27,7 +27,7 @@ fn main() -> Result<(), HandshakeError<io::Error>> {
     let key = Keypair::generate();
 
     let mut stream = AllowStdIo::new(ReadWrite::new(stdin(), stdout()));
-    let o = block_on(client_side(&mut stream, &net_key, &key, &server_pk))?;
+    let o = block_on(client_side(&mut stream, &net_key, &key, &server_pk))?.keys;
 
     let mut v = o.write_key.0.to_vec();
     v.extend_from_slice(&o.write_starting_nonce.0);
@@ -35,7 +35,7 @@ fn main() -> Result<(), HandshakeError<io::Error>> {
     v.extend_from_slice(&o.read_starting_nonce.0);
     assert_eq!(v.len(), 112);
 
-    stdout().write(&v).unwrap();
+    stdout().write_all(&v).unwrap();
     stdout().flush().unwrap();
 
     Ok(())
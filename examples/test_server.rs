@@ -29,7 +29,7 @@ fn main() -> Result<(), HandshakeError<io::Error>> {
     assert_eq!(key.public, pk);
 
     let mut stream = AllowStdIo::new(ReadWrite::new(stdin(), stdout()));
-    let o = block_on(server_side(&mut stream, &net_key, &key))?;
+    let o = block_on(server_side(&mut stream, &net_key, &key))?.keys;
 
     let mut v = o.write_key.0.to_vec();
     v.extend_from_slice(&o.write_starting_nonce.0);
@@ -37,7 +37,7 @@ fn main() -> Result<(), HandshakeError<io::Error>> {
     v.extend_from_slice(&o.read_starting_nonce.0);
     assert_eq!(v.len(), 112);
 
-    stdout().write(&v).unwrap();
+    stdout().write_all(&v).unwrap();
     stdout().flush().unwrap();
 
     Ok(())
@@ -0,0 +1,16 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use ssb_handshake::sync::client_side;
+use ssb_handshake_fuzz::*;
+
+// Arbitrary bytes from the "server".
+fuzz_target!(|data: &[u8]| {
+    let keys = Keys::new();
+    let _ = client_side(
+        ReplayStream::new(data),
+        &keys.net_key,
+        &keys.client,
+        &keys.server.public,
+        client_eph_keypair(),
+    );
+});
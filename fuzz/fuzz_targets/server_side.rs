@@ -0,0 +1,15 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use ssb_handshake::sync::server_side;
+use ssb_handshake_fuzz::*;
+
+// Arbitrary bytes from the "client".
+fuzz_target!(|data: &[u8]| {
+    let keys = Keys::new();
+    let _ = server_side(
+        ReplayStream::new(data),
+        &keys.net_key,
+        &keys.server,
+        server_eph_keypair(),
+    );
+});
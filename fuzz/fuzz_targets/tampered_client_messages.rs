@@ -0,0 +1,21 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use ssb_handshake::sync::server_side;
+use ssb_handshake_fuzz::*;
+
+// A valid client hello and client auth, with some bits flipped.
+// The server must accept them iff they're unchanged.
+fuzz_target!(|mutations: &[u8]| {
+    let keys = Keys::new();
+    let valid = keys.exchange().c2s;
+    let mut msgs = valid.clone();
+    tamper(&mut msgs, mutations);
+
+    let r = server_side(
+        ReplayStream::new(&msgs),
+        &keys.net_key,
+        &keys.server,
+        server_eph_keypair(),
+    );
+    assert_eq!(r.is_ok(), msgs == valid);
+});
@@ -0,0 +1,22 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use ssb_handshake::sync::client_side;
+use ssb_handshake_fuzz::*;
+
+// A valid server hello and server accept, with some bits flipped.
+// The client must accept them iff they're unchanged.
+fuzz_target!(|mutations: &[u8]| {
+    let keys = Keys::new();
+    let valid = keys.exchange().s2c;
+    let mut msgs = valid.clone();
+    tamper(&mut msgs, mutations);
+
+    let r = client_side(
+        ReplayStream::new(&msgs),
+        &keys.net_key,
+        &keys.client,
+        &keys.server.public,
+        client_eph_keypair(),
+    );
+    assert_eq!(r.is_ok(), msgs == valid);
+});
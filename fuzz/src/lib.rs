@@ -0,0 +1,15 @@
+//! Shared setup for the fuzz targets.
+//!
+//! Every target uses the fixed keys from `ssb_handshake::testing`, so that a
+//! recorded transcript of a valid handshake can be replayed against either side.
+
+pub use ssb_handshake::testing::*;
+
+/// Flip bits in `bytes` as described by `mutations`:
+/// each 3-byte chunk is `(offset_hi, offset_lo, xor)`.
+pub fn tamper(bytes: &mut [u8], mutations: &[u8]) {
+    for m in mutations.chunks_exact(3) {
+        let i = u16::from_be_bytes([m[0], m[1]]) as usize % bytes.len();
+        bytes[i] ^= m[2];
+    }
+}
@@ -0,0 +1,250 @@
+//! `shs`: generate keys, perform handshakes, and diagnose failed handshakes.
+
+use ssb_crypto::{Keypair, NetworkKey};
+use ssb_handshake::multiserver::{self, Address, Transport};
+use ssb_handshake::net::{ListenerOptions, ShsListener};
+use ssb_handshake::secret_file;
+use ssb_handshake::transcript::TranscriptRecorder;
+use ssb_handshake::{
+    client_side_with, ClientOptions, FeedId, HandshakeError, Role, ServerConfig, Stage, Timeouts,
+};
+use std::env;
+use std::error::Error;
+use std::path::PathBuf;
+use std::process::exit;
+use std::time::Duration;
+use tokio_util::compat::TokioAsyncReadCompatExt;
+
+const USAGE: &str = "\
+Usage: shs <command> [options]
+
+Commands:
+  generate [--out PATH]      Generate a keypair, and print it (or write it to PATH)
+                             in the secret file format.
+  connect ADDRESS            Handshake with the peer at the multiserver ADDRESS, and
+                             print the peer id and session keys.
+  listen HOST:PORT           Accept handshakes, and log each one.
+  check ADDRESS              Handshake with the peer at the net: ADDRESS, and explain
+                             which step failed, if any, printing the messages exchanged.
+
+Options:
+  --secret PATH              Secret file to use as our identity (default ~/.ssb/secret).
+                             connect and check use a throwaway identity if it doesn't exist.
+  --net-key KEY              Network key, in base64 (default: the main SSB network).
+  --show-keys                Print session keys instead of redacting them.
+";
+
+struct Options {
+    args: Vec<String>,
+    out: Option<PathBuf>,
+    secret: Option<PathBuf>,
+    net_key: NetworkKey,
+    show_keys: bool,
+}
+
+fn main() {
+    let mut argv = env::args().skip(1);
+    let command = argv.next().unwrap_or_default();
+    let opts = match parse_options(argv) {
+        Ok(o) => o,
+        Err(e) => usage_error(&e),
+    };
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let r = match (command.as_str(), opts.args.as_slice()) {
+        ("generate", []) => generate(&opts),
+        ("connect", [addr]) => rt.block_on(connect(&opts, addr)),
+        ("listen", [addr]) => rt.block_on(listen(&opts, addr)),
+        ("check", [addr]) => rt.block_on(check(&opts, addr)),
+        _ => usage_error("unknown command, or wrong number of arguments"),
+    };
+    if let Err(e) = r {
+        eprintln!("error: {}", e);
+        exit(1);
+    }
+}
+
+fn usage_error(msg: &str) -> ! {
+    eprintln!("{}\n\n{}", msg, USAGE);
+    exit(2);
+}
+
+fn parse_options(mut argv: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut opts = Options {
+        args: Vec::new(),
+        out: None,
+        secret: None,
+        net_key: NetworkKey::SSB_MAIN_NET,
+        show_keys: false,
+    };
+    while let Some(arg) = argv.next() {
+        let mut value = || argv.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--out" => opts.out = Some(value()?.into()),
+            "--secret" => opts.secret = Some(value()?.into()),
+            "--net-key" => {
+                opts.net_key = base64::decode(value()?)
+                    .ok()
+                    .and_then(|k| NetworkKey::from_slice(&k))
+                    .ok_or("invalid network key")?
+            }
+            "--show-keys" => opts.show_keys = true,
+            a if a.starts_with("--") => return Err(format!("unknown option {}", a)),
+            _ => opts.args.push(arg),
+        }
+    }
+    Ok(opts)
+}
+
+/// Our identity: the given (or default) secret file, or if `throwaway` is set and
+/// there's no such file, a newly generated keypair.
+fn identity(opts: &Options, throwaway: bool) -> Result<Keypair, Box<dyn Error>> {
+    let path = opts
+        .secret
+        .clone()
+        .or_else(secret_file::default_path)
+        .ok_or("no --secret given, and HOME isn't set")?;
+    if throwaway && opts.secret.is_none() && !path.exists() {
+        eprintln!(
+            "{} doesn't exist; using a throwaway identity",
+            path.display()
+        );
+        return Ok(Keypair::generate());
+    }
+    secret_file::load(&path).map_err(|e| format!("{}: {}", path.display(), e).into())
+}
+
+fn generate(opts: &Options) -> Result<(), Box<dyn Error>> {
+    match &opts.out {
+        Some(path) => {
+            let keypair = secret_file::create(path)?;
+            println!("{}", FeedId(keypair.public));
+        }
+        None => print!("{}", secret_file::format(&Keypair::generate())),
+    }
+    Ok(())
+}
+
+fn show_key(opts: &Options, bytes: &[u8]) -> String {
+    if opts.show_keys {
+        base64::encode(bytes)
+    } else {
+        "<redacted>".to_string()
+    }
+}
+
+async fn connect(opts: &Options, addr: &str) -> Result<(), Box<dyn Error>> {
+    let addr: Address = addr.parse()?;
+    let keypair = identity(opts, true)?;
+    let (_, o) = multiserver::connect(&addr, &opts.net_key, &keypair).await?;
+
+    println!("peer:        {}", o.keys.peer_id());
+    println!("write key:   {}", show_key(opts, &o.keys.write_key.0));
+    println!(
+        "write nonce: {}",
+        show_key(opts, &o.keys.write_starting_nonce.0)
+    );
+    println!("read key:    {}", show_key(opts, &o.keys.read_key.0));
+    println!(
+        "read nonce:  {}",
+        show_key(opts, &o.keys.read_starting_nonce.0)
+    );
+    Ok(())
+}
+
+async fn listen(opts: &Options, addr: &str) -> Result<(), Box<dyn Error>> {
+    let keypair = identity(opts, false)?;
+    println!("listening as {}", FeedId(keypair.public));
+
+    let config = ServerConfig::new(opts.net_key.clone(), keypair)
+        .ok_or("the identity's public key can't be used for a handshake")?;
+    let mut listener = ShsListener::bind(addr, ListenerOptions::new(config)).await?;
+    println!("listening on {}", listener.local_addr());
+    loop {
+        match listener.accept().await {
+            Ok((_, o, addr)) => println!("{}: handshake with {}", addr, o.keys.peer_id()),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+}
+
+async fn check(opts: &Options, addr: &str) -> Result<(), Box<dyn Error>> {
+    let addr: Address = addr.parse()?;
+    if addr.transport != Transport::Net {
+        return Err(
+            "only net addresses can be checked; ws and onion addresses can't be dialed".into(),
+        );
+    }
+    let keypair = identity(opts, true)?;
+
+    let addrs = multiserver::resolve(&addr.host, addr.port)
+        .await
+        .map_err(|e| format!("looking up {} failed: {}", addr.host, e))?;
+    let stream = tokio::net::TcpStream::connect(&addrs[..])
+        .await
+        .map_err(|e| format!("TCP connection failed: {}", e))?;
+
+    let mut recorder = TranscriptRecorder::new(stream.compat(), Role::Client);
+    let timeouts = Timeouts {
+        handshake: Some(Duration::from_secs(10)),
+        message: None,
+    };
+    let options = ClientOptions::new(&opts.net_key, &keypair, &addr.key).timeouts(timeouts);
+    let r = client_side_with(&mut recorder, options).await;
+
+    let err = match r {
+        Ok(o) => {
+            println!("ok: handshake with {} succeeded", o.keys.peer_id());
+            return Ok(());
+        }
+        Err(e) => e,
+    };
+
+    let t = recorder.transcript();
+    use HandshakeError::*;
+    let stage = match &err {
+        ServerHelloDeserializeFailed | ServerHelloVerifyFailed => Stage::ServerHello,
+        SharedAInvalid | SharedBInvalid | SharedCInvalid => Stage::ServerHello,
+        ServerAcceptDeserializeFailed | ServerAcceptVerifyFailed => Stage::ServerAccept,
+        Timeout { stage } => *stage,
+        // The first message that wasn't completely sent or received.
+        _ => Stage::ALL
+            .iter()
+            .copied()
+            .find(|s| {
+                !t.messages
+                    .iter()
+                    .any(|m| m.stage == *s && m.bytes.len() == s.message_size())
+            })
+            .unwrap_or(Stage::ServerAccept),
+    };
+
+    println!("messages exchanged:\n{}", t);
+    let mut msg = format!("handshake failed at {}: {}", stage, err);
+    if let Some(hint) = hint(stage, &err) {
+        msg = format!("{}\n{}", msg, hint);
+    }
+    Err(msg.into())
+}
+
+fn hint(stage: Stage, err: &HandshakeError<std::io::Error>) -> Option<&'static str> {
+    use HandshakeError::*;
+    match (stage, err) {
+        (_, ServerHelloVerifyFailed) => Some("The peer is on a different network (see --net-key)."),
+        (_, ServerAcceptVerifyFailed) => Some("The peer's accept message is invalid."),
+        (_, Timeout { .. }) => Some("The peer stopped responding."),
+        (Stage::ServerHello, _) => Some(
+            "The peer closed the connection after our hello. It may be on a different \
+             network (see --net-key), or not speak the secret handshake.",
+        ),
+        (Stage::ServerAccept, _) => Some(
+            "The peer closed the connection after our auth message. The address may have \
+             the wrong public key, or the peer doesn't let us in.",
+        ),
+        _ => None,
+    }
+}
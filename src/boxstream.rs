@@ -0,0 +1,349 @@
+//! Box-stream encryption, for use after a successful handshake.
+//!
+//! Each chunk of data is sent as a sealed 34-byte header (containing the
+//! length of the body and the body's auth tag), followed by the sealed body.
+//! The stream is ended by a "goodbye" header, whose contents are all zeros.
+//! See the [protocol guide](https://ssbc.github.io/scuttlebutt-protocol-guide/#box-stream)
+//! for the details.
+
+use crate::crypto::outcome::HandshakeKeys;
+
+use core::cmp::min;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use futures_io::{AsyncRead, AsyncWrite};
+use futures_util::io::{AsyncReadExt, ReadHalf, WriteHalf};
+use futures_util::ready;
+use ssb_crypto::secretbox::{Hmac, Key, Nonce};
+use std::io::{self, ErrorKind};
+
+#[cfg(feature = "getrandom")]
+use crate::error::HandshakeError;
+#[cfg(feature = "getrandom")]
+use ssb_crypto::{Keypair, NetworkKey, PublicKey};
+
+/// Maximum number of plaintext bytes in a single box.
+pub const MAX_BOX_BODY_SIZE: usize = 4096;
+
+const HMAC_SIZE: usize = 16;
+const HEADER_PLAINTEXT_SIZE: usize = 2 + HMAC_SIZE;
+const HEADER_SIZE: usize = HMAC_SIZE + HEADER_PLAINTEXT_SIZE;
+
+/// Split the stream into encrypting and decrypting halves, using the keys
+/// and nonces from a successful handshake.
+pub fn box_stream<S>(
+    stream: S,
+    keys: HandshakeKeys,
+) -> (BoxReader<ReadHalf<S>>, BoxWriter<WriteHalf<S>>)
+where
+    S: AsyncRead + AsyncWrite,
+{
+    // HandshakeKeys wipes its keys on drop, when the `zeroize` feature is enabled,
+    // so they're copied out rather than moved.
+    let (r, w) = stream.split();
+    (
+        BoxReader::new(r, Key(keys.read_key.0), Nonce(keys.read_starting_nonce.0)),
+        BoxWriter::new(w, Key(keys.write_key.0), Nonce(keys.write_starting_nonce.0)),
+    )
+}
+
+/// Perform the client side of the handshake, and wrap the stream in box-stream encryption.
+/// Returns the reader and writer halves, and the server's public key.
+#[cfg(feature = "getrandom")]
+pub async fn client_side<S>(
+    mut stream: S,
+    net_key: &NetworkKey,
+    keypair: &Keypair,
+    server_pk: &PublicKey,
+) -> Result<(BoxReader<ReadHalf<S>>, BoxWriter<WriteHalf<S>>, PublicKey), HandshakeError<io::Error>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let keys = crate::client_side(&mut stream, net_key, keypair, server_pk)
+        .await?
+        .keys;
+    let peer_key = keys.peer_key;
+    let (r, w) = box_stream(stream, keys);
+    Ok((r, w, peer_key))
+}
+
+/// Perform the server side of the handshake, and wrap the stream in box-stream encryption.
+/// Returns the reader and writer halves, and the client's public key.
+#[cfg(feature = "getrandom")]
+pub async fn server_side<S>(
+    mut stream: S,
+    net_key: &NetworkKey,
+    keypair: &Keypair,
+) -> Result<(BoxReader<ReadHalf<S>>, BoxWriter<WriteHalf<S>>, PublicKey), HandshakeError<io::Error>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let keys = crate::server_side(&mut stream, net_key, keypair)
+        .await?
+        .keys;
+    let peer_key = keys.peer_key;
+    let (r, w) = box_stream(stream, keys);
+    Ok((r, w, peer_key))
+}
+
+fn increment_nonce(nonce: &mut Nonce) {
+    for b in nonce.0.iter_mut().rev() {
+        let (v, overflow) = b.overflowing_add(1);
+        *b = v;
+        if !overflow {
+            break;
+        }
+    }
+}
+
+/// Returns the nonce to use, and increments the given one.
+fn next_nonce(nonce: &mut Nonce) -> Nonce {
+    let n = Nonce(nonce.0);
+    increment_nonce(nonce);
+    n
+}
+
+/// A box-stream key; wiped on drop if the `zeroize` feature is enabled.
+struct BoxKey(Key);
+wipe_on_drop!(BoxKey);
+
+fn hmac_from_slice(b: &[u8]) -> Hmac {
+    let mut h = [0; HMAC_SIZE];
+    h.copy_from_slice(b);
+    Hmac(h)
+}
+
+/// Encrypts everything written to it, and sends the goodbye message on close.
+pub struct BoxWriter<W> {
+    inner: W,
+    key: BoxKey,
+    nonce: Nonce,
+    out: Vec<u8>,
+    pos: usize,
+    goodbye_sent: bool,
+}
+
+impl<W> BoxWriter<W> {
+    pub fn new(inner: W, key: Key, nonce: Nonce) -> BoxWriter<W> {
+        BoxWriter {
+            inner,
+            key: BoxKey(key),
+            nonce,
+            out: Vec::new(),
+            pos: 0,
+            goodbye_sent: false,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    fn push_box(&mut self, body: &[u8]) {
+        let header_nonce = next_nonce(&mut self.nonce);
+        let body_nonce = next_nonce(&mut self.nonce);
+
+        let start = self.out.len();
+        self.out.resize(start + HEADER_SIZE, 0);
+        self.out.extend_from_slice(body);
+        let body_hmac = self
+            .key
+            .0
+            .seal(&mut self.out[start + HEADER_SIZE..], &body_nonce);
+
+        let mut header = [0; HEADER_PLAINTEXT_SIZE];
+        header[..2].copy_from_slice(&(body.len() as u16).to_be_bytes());
+        header[2..].copy_from_slice(&body_hmac.0);
+        self.push_header(header, &header_nonce, start);
+    }
+
+    fn push_goodbye(&mut self) {
+        let nonce = next_nonce(&mut self.nonce);
+        let start = self.out.len();
+        self.out.resize(start + HEADER_SIZE, 0);
+        self.push_header([0; HEADER_PLAINTEXT_SIZE], &nonce, start);
+    }
+
+    fn push_header(&mut self, mut header: [u8; HEADER_PLAINTEXT_SIZE], nonce: &Nonce, at: usize) {
+        let hmac = self.key.0.seal(&mut header, nonce);
+        self.out[at..at + HMAC_SIZE].copy_from_slice(&hmac.0);
+        self.out[at + HMAC_SIZE..at + HEADER_SIZE].copy_from_slice(&header);
+    }
+}
+
+impl<W: AsyncWrite + Unpin> BoxWriter<W> {
+    /// Write out any encrypted bytes that haven't been written yet.
+    fn poll_write_out(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.pos < self.out.len() {
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.out[self.pos..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(ErrorKind::WriteZero.into()));
+            }
+            self.pos += n;
+        }
+        self.out.clear();
+        self.pos = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for BoxWriter<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.goodbye_sent {
+            return Poll::Ready(Err(io::Error::new(
+                ErrorKind::BrokenPipe,
+                "box stream is closed",
+            )));
+        }
+        ready!(this.poll_write_out(cx))?;
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let n = min(buf.len(), MAX_BOX_BODY_SIZE);
+        this.push_box(&buf[..n]);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_out(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.goodbye_sent {
+            this.push_goodbye();
+            this.goodbye_sent = true;
+        }
+        ready!(this.poll_write_out(cx))?;
+        Pin::new(&mut this.inner).poll_close(cx)
+    }
+}
+
+enum ReadState {
+    Header,
+    Body { len: usize, hmac: Hmac },
+    Goodbye,
+}
+
+/// Decrypts everything read from it.
+/// Reads return 0 bytes once the peer has sent the goodbye message.
+pub struct BoxReader<R> {
+    inner: R,
+    key: BoxKey,
+    nonce: Nonce,
+    state: ReadState,
+    buf: Vec<u8>,
+    filled: usize,
+    plain: Vec<u8>,
+    pos: usize,
+}
+
+impl<R> BoxReader<R> {
+    pub fn new(inner: R, key: Key, nonce: Nonce) -> BoxReader<R> {
+        BoxReader {
+            inner,
+            key: BoxKey(key),
+            nonce,
+            state: ReadState::Header,
+            buf: Vec::new(),
+            filled: 0,
+            plain: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn open_header(&mut self) -> io::Result<ReadState> {
+        let nonce = next_nonce(&mut self.nonce);
+        let hmac = hmac_from_slice(&self.buf[..HMAC_SIZE]);
+        let header = &mut self.buf[HMAC_SIZE..HEADER_SIZE];
+        if !self.key.0.open(header, &hmac, &nonce) {
+            return Err(invalid_data("failed to decrypt box header"));
+        }
+        if header.iter().all(|b| *b == 0) {
+            return Ok(ReadState::Goodbye);
+        }
+        let len = u16::from_be_bytes([header[0], header[1]]) as usize;
+        if len > MAX_BOX_BODY_SIZE {
+            return Err(invalid_data("box body is too long"));
+        }
+        Ok(ReadState::Body {
+            len,
+            hmac: hmac_from_slice(&header[2..]),
+        })
+    }
+
+    fn open_body(&mut self, hmac: &Hmac) -> io::Result<()> {
+        let nonce = next_nonce(&mut self.nonce);
+        if !self.key.0.open(&mut self.buf, hmac, &nonce) {
+            return Err(invalid_data("failed to decrypt box body"));
+        }
+        core::mem::swap(&mut self.plain, &mut self.buf);
+        self.pos = 0;
+        Ok(())
+    }
+}
+
+impl<R: AsyncRead + Unpin> BoxReader<R> {
+    /// Read until `self.buf` holds exactly `n` bytes.
+    fn poll_fill(&mut self, cx: &mut Context<'_>, n: usize) -> Poll<io::Result<()>> {
+        if self.buf.len() != n {
+            self.buf.resize(n, 0);
+        }
+        while self.filled < n {
+            let m = ready!(Pin::new(&mut self.inner).poll_read(cx, &mut self.buf[self.filled..]))?;
+            if m == 0 {
+                return Poll::Ready(Err(ErrorKind::UnexpectedEof.into()));
+            }
+            self.filled += m;
+        }
+        self.filled = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for BoxReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        out: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            if this.pos < this.plain.len() {
+                let n = min(out.len(), this.plain.len() - this.pos);
+                out[..n].copy_from_slice(&this.plain[this.pos..this.pos + n]);
+                this.pos += n;
+                return Poll::Ready(Ok(n));
+            }
+
+            match &this.state {
+                ReadState::Goodbye => return Poll::Ready(Ok(0)),
+                ReadState::Header => {
+                    ready!(this.poll_fill(cx, HEADER_SIZE))?;
+                    this.state = this.open_header()?;
+                }
+                ReadState::Body { len, hmac } => {
+                    let (len, hmac) = (*len, hmac_from_slice(&hmac.0));
+                    ready!(this.poll_fill(cx, len))?;
+                    this.open_body(&hmac)?;
+                    this.state = ReadState::Header;
+                }
+            }
+        }
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg)
+}
@@ -1,9 +1,33 @@
 use zerocopy::LayoutVerified;
 pub use zerocopy::{AsBytes, FromBytes};
 
-pub fn as_ref<T: FromBytes>(b: &[u8]) -> &T {
-    LayoutVerified::<&[u8], T>::new(b).unwrap().into_ref()
+/// `None` if `b` isn't exactly the size of `T`.
+pub fn as_ref<T: FromBytes>(b: &[u8]) -> Option<&T> {
+    LayoutVerified::<&[u8], T>::new(b).map(|v| v.into_ref())
 }
-pub fn as_mut<T: AsBytes + FromBytes>(b: &mut [u8]) -> &mut T {
-    LayoutVerified::<&mut [u8], T>::new(b).unwrap().into_mut()
+/// `None` if `b` isn't exactly the size of `T`.
+pub fn as_mut<T: AsBytes + FromBytes>(b: &mut [u8]) -> Option<&mut T> {
+    LayoutVerified::<&mut [u8], T>::new(b).map(|v| v.into_mut())
+}
+
+/// Overwrite secret bytes with zeros, if the `zeroize` feature is enabled.
+#[inline]
+pub fn wipe(b: &mut [u8]) {
+    #[cfg(feature = "zeroize")]
+    zeroize::Zeroize::zeroize(b);
+    #[cfg(not(feature = "zeroize"))]
+    let _ = b;
+}
+
+/// Implement `Drop` for wrappers around secret byte arrays (`Foo(Bar([u8; N]))`),
+/// wiping the bytes if the `zeroize` feature is enabled.
+macro_rules! wipe_on_drop {
+    ($($t:ty),*) => {$(
+        #[cfg(feature = "zeroize")]
+        impl Drop for $t {
+            fn drop(&mut self) {
+                crate::bytes::wipe(&mut self.0 .0);
+            }
+        }
+    )*};
 }
@@ -1,94 +1,57 @@
-use crate::bytes::{as_mut, as_ref};
-use crate::crypto::outcome::HandshakeKeys;
-use crate::crypto::{keys::*, message::*, outcome::*, shared_secret::*};
+use crate::crypto::outcome::HandshakeOutcome;
 use crate::error::HandshakeError;
-use crate::util::send;
+use crate::handshake::ClientHandshake;
+use crate::options::ClientOptions;
+use crate::util::{close_on_err, drive, Limits, Timeouts};
 
-use ssb_crypto::{ephemeral::generate_ephemeral_keypair, Keypair, NetworkKey, PublicKey};
+#[cfg(feature = "getrandom")]
+use ssb_crypto::{Keypair, NetworkKey, PublicKey};
 
-use core::mem::size_of;
 use futures_io::{AsyncRead, AsyncWrite};
-use futures_util::io::{AsyncReadExt, AsyncWriteExt};
 use std::io;
 
 /// Perform the client side of the handshake over an `AsyncRead + AsyncWrite` stream.
 /// Closes the stream on handshake failure.
+#[cfg(feature = "getrandom")]
 pub async fn client_side<S>(
-    mut stream: S,
+    stream: S,
     net_key: &NetworkKey,
     keypair: &Keypair,
     server_pk: &PublicKey,
-) -> Result<HandshakeKeys, HandshakeError<io::Error>>
+) -> Result<HandshakeOutcome, HandshakeError<io::Error>>
 where
     S: AsyncRead + AsyncWrite + Unpin,
 {
-    let r = try_client_side(&mut stream, net_key, keypair, server_pk).await;
-    if r.is_err() {
-        stream.close().await.unwrap_or(());
-    }
-    r
+    client_side_with(stream, ClientOptions::new(net_key, keypair, server_pk)).await
 }
 
-async fn try_client_side<S>(
+/// Perform the client side of the handshake, with the given `ClientOptions`
+/// (timeouts, prepared keys, ephemeral keypair, etc).
+/// Closes the stream on handshake failure.
+///
+/// Without the `getrandom` feature, the options must include an ephemeral keypair
+/// or an rng.
+pub async fn client_side_with<S>(
     mut stream: S,
-    net_key: &NetworkKey,
-    keypair: &Keypair,
-    server_pk: &PublicKey,
-) -> Result<HandshakeKeys, HandshakeError<io::Error>>
+    mut options: ClientOptions<'_>,
+) -> Result<HandshakeOutcome, HandshakeError<io::Error>>
 where
     S: AsyncRead + AsyncWrite + Unpin,
 {
-    use HandshakeError::*;
-
-    let server_pk = ServerPublicKey(*server_pk);
-    let (eph_pk, eph_sk) = {
-        let (p, s) = generate_ephemeral_keypair();
-        (ClientEphPublicKey(p), ClientEphSecretKey(s))
-    };
-
-    send(&mut stream, ClientHello::new(&eph_pk, &net_key)).await?;
-
-    let server_eph_pk = {
-        let mut buf = [0u8; size_of::<ServerHello>()];
-        stream.read_exact(&mut buf).await?;
-        as_mut::<ServerHello>(&mut buf)
-            .verify(&net_key)
-            .ok_or(ServerHelloVerifyFailed)?
-    };
-
-    // Derive shared secrets
-    let shared_a = SharedA::client_side(&eph_sk, &server_eph_pk).ok_or(SharedAInvalid)?;
-    let shared_b = SharedB::client_side(&eph_sk, &server_pk).ok_or(SharedBInvalid)?;
-    let shared_c = SharedC::client_side(&keypair, &server_eph_pk).ok_or(SharedCInvalid)?;
-
-    // Send client auth
-    send(
-        &mut stream,
-        ClientAuth::new(&keypair, &server_pk, &net_key, &shared_a, &shared_b),
-    )
-    .await?;
-
-    let mut buf = [0u8; size_of::<ServerAccept>()];
-    stream.read_exact(&mut buf).await?;
-    as_ref::<ServerAccept>(&buf)
-        .verify(
-            &keypair, &server_pk, &net_key, &shared_a, &shared_b, &shared_c,
-        )
-        .ok_or(ServerAcceptVerifyFailed)?;
-
-    Ok(HandshakeKeys {
-        read_key: server_to_client_key(
-            &ClientPublicKey(keypair.public),
-            &net_key,
-            &shared_a,
-            &shared_b,
-            &shared_c,
-        ),
-        read_starting_nonce: starting_nonce(&net_key, &eph_pk.0),
-
-        write_key: client_to_server_key(&server_pk, &net_key, &shared_a, &shared_b, &shared_c),
-        write_starting_nonce: starting_nonce(&net_key, &server_eph_pk.0),
+    let hs = options.handshake();
+    let r = try_client_side(&mut stream, hs, &options.timeouts).await;
+    close_on_err(&mut stream, r).await
+}
 
-        peer_key: server_pk.0,
-    })
+async fn try_client_side<S>(
+    mut stream: S,
+    mut hs: ClientHandshake<'_>,
+    timeouts: &Timeouts,
+) -> Result<HandshakeOutcome, HandshakeError<io::Error>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let limits = Limits::start(timeouts);
+    drive(&mut stream, &mut hs, &limits).await?;
+    Ok(hs.into_outcome().unwrap())
 }
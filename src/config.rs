@@ -0,0 +1,93 @@
+//! Long-term keys prepared once, for reuse across many handshakes.
+//!
+//! Shared secrets B and C are derived from the long-term ed25519 keys, which
+//! first have to be converted to curve25519. A [`ServerConfig`] (or a
+//! [`ClientConfig`], for each server the client connects to) does those
+//! conversions up front, instead of on every handshake.
+
+use crate::crypto::keys::{ClientCurveSecretKey, ServerCurvePublicKey, ServerCurveSecretKey};
+
+use curve25519_dalek::edwards::CompressedEdwardsY;
+use ssb_crypto::ephemeral::{sk_to_curve, EphPublicKey};
+use ssb_crypto::{Keypair, NetworkKey, PublicKey};
+
+/// The server's network key and long-term identity.
+pub struct ServerConfig {
+    pub(crate) net_key: NetworkKey,
+    pub(crate) keypair: Keypair,
+    pub(crate) curve_sk: ServerCurveSecretKey,
+}
+
+impl ServerConfig {
+    /// Returns `None` if the keypair's secret key can't be converted to curve25519.
+    pub fn new(net_key: NetworkKey, keypair: Keypair) -> Option<ServerConfig> {
+        let curve_sk = ServerCurveSecretKey(sk_to_curve(&keypair.secret)?);
+        Some(ServerConfig {
+            net_key,
+            keypair,
+            curve_sk,
+        })
+    }
+
+    pub fn net_key(&self) -> &NetworkKey {
+        &self.net_key
+    }
+
+    pub fn keypair(&self) -> &Keypair {
+        &self.keypair
+    }
+}
+
+/// The client's network key and long-term identity, and the long-term public key
+/// of the server it connects to.
+pub struct ClientConfig {
+    pub(crate) net_key: NetworkKey,
+    pub(crate) keypair: Keypair,
+    pub(crate) server_pk: PublicKey,
+    pub(crate) curve_sk: ClientCurveSecretKey,
+    pub(crate) server_curve_pk: ServerCurvePublicKey,
+}
+
+impl ClientConfig {
+    /// Returns `None` if the keypair's secret key or the server's public key
+    /// can't be converted to curve25519.
+    pub fn new(
+        net_key: NetworkKey,
+        keypair: Keypair,
+        server_pk: PublicKey,
+    ) -> Option<ClientConfig> {
+        let curve_sk = ClientCurveSecretKey(sk_to_curve(&keypair.secret)?);
+        let server_curve_pk = ServerCurvePublicKey(pk_to_curve(&server_pk)?);
+        Some(ClientConfig {
+            net_key,
+            keypair,
+            server_pk,
+            curve_sk,
+            server_curve_pk,
+        })
+    }
+
+    pub fn net_key(&self) -> &NetworkKey {
+        &self.net_key
+    }
+
+    pub fn keypair(&self) -> &Keypair {
+        &self.keypair
+    }
+
+    pub fn server_public_key(&self) -> &PublicKey {
+        &self.server_pk
+    }
+}
+
+/// Convert an ed25519 public key to curve25519.
+///
+/// Like `ssb_crypto::ephemeral::derive_shared_secret_pk`, rejects keys that
+/// aren't valid points, and small-order points.
+fn pk_to_curve(pk: &PublicKey) -> Option<EphPublicKey> {
+    let point = CompressedEdwardsY(pk.0).decompress()?;
+    if point.is_small_order() {
+        return None;
+    }
+    Some(EphPublicKey(point.to_montgomery().to_bytes()))
+}
@@ -6,3 +6,22 @@ pub mod keys;
 pub mod message;
 pub mod outcome;
 pub mod shared_secret;
+
+use crate::bytes::wipe;
+use ssb_crypto::{hash, Hash};
+
+/// sha256 of the concatenation of the given byte slices (128 bytes at most).
+///
+/// Used instead of hashing a `#[repr(C)]` struct of cloned secrets,
+/// so that the only copy of the secrets is wiped afterwards.
+pub(crate) fn hash_parts(parts: &[&[u8]]) -> Hash {
+    let mut buf = [0u8; 128];
+    let mut len = 0;
+    for p in parts {
+        buf[len..len + p.len()].copy_from_slice(p);
+        len += p.len();
+    }
+    let h = hash(&buf[..len]);
+    wipe(&mut buf);
+    h
+}
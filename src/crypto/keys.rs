@@ -35,3 +35,20 @@ pub struct ServerEphPublicKey(pub EphPublicKey);
 
 /// Server ephemeral secret key
 pub struct ServerEphSecretKey(pub EphSecretKey);
+
+/// Client's long-term secret key, converted to curve25519.
+pub struct ClientCurveSecretKey(pub EphSecretKey);
+
+/// Server's long-term public key, converted to curve25519.
+#[derive(Copy, Clone)]
+pub struct ServerCurvePublicKey(pub EphPublicKey);
+
+/// Server's long-term secret key, converted to curve25519.
+pub struct ServerCurveSecretKey(pub EphSecretKey);
+
+wipe_on_drop!(
+    ClientEphSecretKey,
+    ServerEphSecretKey,
+    ClientCurveSecretKey,
+    ServerCurveSecretKey
+);
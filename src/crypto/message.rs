@@ -5,10 +5,11 @@
 //! rather than explicitly creating a buffer and copying the bytes of
 //! each part of the message into it.
 
-use crate::bytes::as_ref;
-use crate::crypto::{keys::*, shared_secret::*};
+use crate::bytes::{as_mut, as_ref, wipe};
+use crate::crypto::{hash_parts, keys::*, shared_secret::*};
+use crate::error::HandshakeError;
 
-use ssb_crypto::hash;
+use core::convert::Infallible;
 use ssb_crypto::secretbox::{self, Hmac, Nonce};
 use ssb_crypto::{Keypair, NetworkAuth, NetworkKey, Signature};
 use zerocopy::{AsBytes, FromBytes};
@@ -28,6 +29,12 @@ impl ClientHello {
         ClientHello(net_key.authenticate(eph_pk.as_bytes()), *eph_pk)
     }
 
+    /// Interpret `b` as a client hello message.
+    /// Fails with `ClientHelloDeserializeFailed` if `b` is the wrong size.
+    pub fn from_bytes(b: &[u8]) -> Result<&ClientHello, HandshakeError<Infallible>> {
+        as_ref(b).ok_or(HandshakeError::ClientHelloDeserializeFailed)
+    }
+
     // assert_nacl_auth_verify(
     //   authenticator: client_hmac,
     //   msg: client_ephemeral_pk,
@@ -57,6 +64,12 @@ impl ServerHello {
         ServerHello(net_key.authenticate(eph_pk.as_bytes()), *eph_pk)
     }
 
+    /// Interpret `b` as a server hello message.
+    /// Fails with `ServerHelloDeserializeFailed` if `b` is the wrong size.
+    pub fn from_bytes(b: &[u8]) -> Result<&ServerHello, HandshakeError<Infallible>> {
+        as_ref(b).ok_or(HandshakeError::ServerHelloDeserializeFailed)
+    }
+
     // assert_nacl_auth_verify(
     //   authenticator: server_hmac,
     //   msg: server_ephemeral_pk,
@@ -98,24 +111,40 @@ impl ClientAuth {
     //     )
     //   )
     // )
+    //
+    // Also returns detached_signature_A (as part of a `ClientProof`),
+    // which the client needs again to verify the server accept message.
     pub fn new(
         kp: &Keypair,
         server_pk: &ServerPublicKey,
         net_key: &NetworkKey,
         sa: &SharedA,
         sb: &SharedB,
-    ) -> ClientAuth {
-        let payload = ClientAuthPayload(
-            ClientSignature(
-                kp.sign(ClientAuthSignData(net_key.clone(), *server_pk, sa.hash()).as_bytes()),
-            ),
-            ClientPublicKey(kp.public),
+    ) -> (ClientAuth, ClientProof) {
+        let sig = ClientSignature(
+            kp.sign(ClientAuthSignData(net_key.clone(), *server_pk, sa.hash()).as_bytes()),
         );
+        let mut payload = ClientAuthPayload(sig, ClientPublicKey(kp.public));
         let mut buf = [0; 96];
         buf.copy_from_slice(payload.as_bytes());
+        wipe(payload.as_bytes_mut());
 
-        let hmac = client_auth_key(net_key, sa, sb).seal(&mut buf, &Nonce::zero());
-        ClientAuth(hmac, buf)
+        let mut key = client_auth_key(net_key, sa, sb);
+        let hmac = key.seal(&mut buf, &Nonce::zero());
+        wipe(&mut key.0);
+        let proof = ClientProof {
+            sig,
+            pk: ClientPublicKey(kp.public),
+        };
+        (ClientAuth(hmac, buf), proof)
+    }
+
+    /// Interpret `b` as a client auth message.
+    /// Fails with `ClientAuthDeserializeFailed` if `b` is the wrong size.
+    ///
+    /// The message is decrypted in place by `verify`, hence the `&mut`.
+    pub fn from_bytes(b: &mut [u8]) -> Result<&mut ClientAuth, HandshakeError<Infallible>> {
+        as_mut(b).ok_or(HandshakeError::ClientAuthDeserializeFailed)
     }
 
     pub fn verify(
@@ -124,16 +153,22 @@ impl ClientAuth {
         net_key: &NetworkKey,
         sa: &SharedA,
         sb: &SharedB,
-    ) -> Option<(ClientSignature, ClientPublicKey)> {
+    ) -> Option<ClientProof> {
         let ClientAuth(hmac, buf) = self;
-        if !client_auth_key(net_key, sa, sb).open(buf, &hmac, &Nonce::zero()) {
+        let mut key = client_auth_key(net_key, sa, sb);
+        let opened = key.open(buf, hmac, &Nonce::zero());
+        wipe(&mut key.0);
+        if !opened {
             return None;
         }
 
-        let ClientAuthPayload(sig, client_pk) = as_ref(buf);
+        let ClientAuthPayload(sig, client_pk) = as_ref(buf)?;
         let signdata = ClientAuthSignData(net_key.clone(), ServerPublicKey(kp.public), sa.hash());
         if client_pk.0.verify(&sig.0, signdata.as_bytes()) {
-            Some((*sig, *client_pk))
+            Some(ClientProof {
+                sig: *sig,
+                pk: *client_pk,
+            })
         } else {
             None
         }
@@ -152,17 +187,21 @@ unsafe impl FromBytes for ClientAuth {
 struct ClientAuthSignData(NetworkKey, ServerPublicKey, SharedAHash);
 
 fn client_auth_key(net_key: &NetworkKey, sa: &SharedA, sb: &SharedB) -> secretbox::Key {
-    #[derive(AsBytes)]
-    #[repr(C)]
-    struct D(NetworkKey, SharedA, SharedB);
-
-    secretbox::Key(hash(D(net_key.clone(), sa.clone(), sb.clone()).as_bytes()).0)
+    secretbox::Key(hash_parts(&[net_key.as_bytes(), sa.as_bytes(), sb.as_bytes()]).0)
 }
 
 #[derive(AsBytes, FromBytes)]
 #[repr(C)]
 struct ClientAuthPayload(ClientSignature, ClientPublicKey);
 
+/// The client's signature and long-term public key from the client auth message,
+/// which the server signs in turn in the server accept message.
+#[derive(Copy, Clone)]
+pub struct ClientProof {
+    pub sig: ClientSignature,
+    pub pk: ClientPublicKey,
+}
+
 /// ## Message 4 (Server to Client)
 #[derive(AsBytes, FromBytes)]
 #[repr(C)]
@@ -170,52 +209,49 @@ pub struct ServerAccept(Hmac, [u8; 64]);
 impl ServerAccept {
     pub fn new(
         kp: &Keypair,
-        client_pk: &ClientPublicKey,
+        proof: &ClientProof,
         net_key: &NetworkKey,
-        client_sig: &ClientSignature,
-        sa: &SharedA,
-        sb: &SharedB,
-        sc: &SharedC,
+        secrets: SharedSecrets,
     ) -> ServerAccept {
         let Signature(mut sig) = kp.sign(
-            ServerAcceptSignData(net_key.clone(), *client_sig, *client_pk, sa.hash()).as_bytes(),
+            ServerAcceptSignData(net_key.clone(), proof.sig, proof.pk, secrets.a.hash()).as_bytes(),
         );
 
-        let hmac = server_accept_key(net_key, sa, sb, sc).seal(&mut sig, &Nonce::zero());
+        let mut key = server_accept_key(net_key, secrets);
+        let hmac = key.seal(&mut sig, &Nonce::zero());
+        wipe(&mut key.0);
         ServerAccept(hmac, sig)
     }
 
-    /// Performed by the client
+    /// Interpret `b` as a server accept message.
+    /// Fails with `ServerAcceptDeserializeFailed` if `b` is the wrong size.
+    pub fn from_bytes(b: &[u8]) -> Result<&ServerAccept, HandshakeError<Infallible>> {
+        as_ref(b).ok_or(HandshakeError::ServerAcceptDeserializeFailed)
+    }
+
+    /// Performed by the client, with the proof returned by `ClientAuth::new`.
     #[must_use]
     pub fn verify(
         &self,
-        kp: &Keypair,
+        proof: &ClientProof,
         server_pk: &ServerPublicKey,
         net_key: &NetworkKey,
-        sa: &SharedA,
-        sb: &SharedB,
-        sc: &SharedC,
+        secrets: SharedSecrets,
     ) -> Option<()> {
         let server_sig = {
             let ServerAccept(hmac, mut buf) = self;
-            if !server_accept_key(net_key, sa, sb, sc).open(&mut buf, &hmac, &Nonce::zero()) {
+            let mut key = server_accept_key(net_key, secrets);
+            let opened = key.open(&mut buf, hmac, &Nonce::zero());
+            wipe(&mut key.0);
+            if !opened {
                 return None;
             }
             ServerSignature(Signature(buf))
         };
-        let client_sig = ClientSignature(
-            kp.sign(ClientAuthSignData(net_key.clone(), *server_pk, sa.hash()).as_bytes()),
-        );
 
         if server_pk.0.verify(
             &server_sig.0,
-            ServerAcceptSignData(
-                net_key.clone(),
-                client_sig,
-                ClientPublicKey(kp.public),
-                sa.hash(),
-            )
-            .as_bytes(),
+            ServerAcceptSignData(net_key.clone(), proof.sig, proof.pk, secrets.a.hash()).as_bytes(),
         ) {
             Some(())
         } else {
@@ -228,14 +264,14 @@ impl ServerAccept {
 #[repr(C)]
 struct ServerAcceptSignData(NetworkKey, ClientSignature, ClientPublicKey, SharedAHash);
 
-fn server_accept_key(
-    net_key: &NetworkKey,
-    a: &SharedA,
-    b: &SharedB,
-    c: &SharedC,
-) -> secretbox::Key {
-    #[derive(AsBytes)]
-    #[repr(C)]
-    struct D(NetworkKey, SharedA, SharedB, SharedC);
-    secretbox::Key(hash(D(net_key.clone(), a.clone(), b.clone(), c.clone()).as_bytes()).0)
+fn server_accept_key(net_key: &NetworkKey, s: SharedSecrets) -> secretbox::Key {
+    secretbox::Key(
+        hash_parts(&[
+            net_key.as_bytes(),
+            s.a.as_bytes(),
+            s.b.as_bytes(),
+            s.c.as_bytes(),
+        ])
+        .0,
+    )
 }
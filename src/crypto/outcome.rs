@@ -1,5 +1,10 @@
+use crate::bytes::wipe;
+use crate::crypto::hash_parts;
 use crate::crypto::keys::*;
 use crate::crypto::shared_secret::*;
+use crate::handshake::Role;
+#[cfg(feature = "std")]
+use crate::FeedId;
 
 use ssb_crypto::ephemeral::EphPublicKey;
 use ssb_crypto::{hash, secretbox::*, Hash, NetworkKey, PublicKey};
@@ -9,6 +14,10 @@ use zerocopy::AsBytes;
 /// used by [ssb-boxstream] to encrypt further communications.
 ///
 /// [ssb-boxstream]: https://crates.io/crates/ssb-boxstream
+///
+/// The secret behind [`export_keying_material`](HandshakeKeys::export_keying_material)
+/// isn't public, so `HandshakeKeys` can't be built with a struct literal;
+/// use [`HandshakeKeys::new`] instead.
 pub struct HandshakeKeys {
     /// Used to decrypt messages sent by the other party.
     pub read_key: Key,
@@ -20,6 +29,122 @@ pub struct HandshakeKeys {
 
     /// PublicKey of the remote peer
     pub peer_key: PublicKey,
+
+    pub(crate) exporter_secret: [u8; 32],
+}
+
+/// Everything learned from a successful handshake: the resulting keys,
+/// plus details that are useful for logging, replay protection and channel binding.
+pub struct HandshakeOutcome {
+    pub keys: HandshakeKeys,
+
+    /// Which side of the handshake we were.
+    pub role: Role,
+
+    /// The network key that was used (relevant when the server accepts several).
+    pub net_key: NetworkKey,
+
+    /// Index of `net_key` in the network keys the server was given
+    /// (see `ServerHandshake::with_network_keys`). Always 0 on the client.
+    pub net_key_index: usize,
+
+    /// Index of the identity the client connected to, in the keypairs the server
+    /// was given (see `ServerHandshake::with_keypairs`). Always 0 on the client.
+    pub keypair_index: usize,
+
+    pub our_eph_key: EphPublicKey,
+    pub peer_eph_key: EphPublicKey,
+
+    /// Hash of the four handshake messages, identical on both sides:
+    /// `h = sha256(h + sha256(message))` for each message in order,
+    /// starting from 32 zero bytes.
+    pub transcript_hash: Hash,
+}
+
+impl HandshakeKeys {
+    /// Build the keys from their parts, eg. when restoring a session, or in tests.
+    /// `exporter_secret` must be as secret as the keys themselves.
+    pub fn new(
+        read_key: Key,
+        read_starting_nonce: Nonce,
+        write_key: Key,
+        write_starting_nonce: Nonce,
+        peer_key: PublicKey,
+        exporter_secret: [u8; 32],
+    ) -> HandshakeKeys {
+        HandshakeKeys {
+            read_key,
+            read_starting_nonce,
+            write_key,
+            write_starting_nonce,
+            peer_key,
+            exporter_secret,
+        }
+    }
+
+    /// The remote peer's public key, as a feed id.
+    #[cfg(feature = "std")]
+    pub fn peer_id(&self) -> FeedId {
+        FeedId(self.peer_key)
+    }
+
+    /// Fill `out` with keying material derived from the handshake's shared secrets,
+    /// for use by higher-level protocols (eg. per-session tokens), in the style of
+    /// the TLS keying material exporter ([RFC 5705](https://tools.ietf.org/html/rfc5705)).
+    ///
+    /// Both sides of the handshake get the same output for the same `label`, `context`
+    /// and `out.len()`. Different labels or contexts give unrelated output,
+    /// which is also unrelated to the box-stream keys.
+    pub fn export_keying_material(&self, label: &[u8], context: &[u8], out: &mut [u8]) {
+        // block_i = sha256(exporter_secret + sha256(label) + sha256(context) + i + out.len())
+        let label = hash(label);
+        let context = hash(context);
+        let len = (out.len() as u32).to_be_bytes();
+        for (i, chunk) in out.chunks_mut(32).enumerate() {
+            let counter = (i as u32).to_be_bytes();
+            let mut block =
+                hash_parts(&[&self.exporter_secret, &label.0, &context.0, &counter, &len]);
+            chunk.copy_from_slice(&block.0[..chunk.len()]);
+            wipe(&mut block.0);
+        }
+    }
+}
+
+/// Shows the peer's feed id; the keys and nonces are left out.
+#[cfg(feature = "std")]
+impl core::fmt::Debug for HandshakeKeys {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("HandshakeKeys")
+            .field("peer_key", &self.peer_id())
+            .finish()
+    }
+}
+
+/// Shows the peer's feed id; the keys and nonces are left out.
+#[cfg(feature = "std")]
+impl core::fmt::Display for HandshakeKeys {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "keys for {} (redacted)", self.peer_id())
+    }
+}
+
+#[cfg(feature = "std")]
+impl core::fmt::Debug for HandshakeOutcome {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("HandshakeOutcome")
+            .field("keys", &self.keys)
+            .field("role", &self.role)
+            .finish()
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for HandshakeKeys {
+    fn drop(&mut self) {
+        wipe(&mut self.read_key.0);
+        wipe(&mut self.write_key.0);
+        wipe(&mut self.exporter_secret);
+    }
 }
 
 fn build_shared_key(
@@ -32,17 +157,35 @@ fn build_shared_key(
     // c2s: sha256( sha256(sha256(net_key + a + b + c)) + server_pk)
     // s2c: sha256( sha256(sha256(net_key + a + b + c)) + client_pk)
 
-    let double_hash = {
-        #[derive(AsBytes)]
-        #[repr(C)]
-        struct D(NetworkKey, SharedA, SharedB, SharedC);
-        hash(hash(D(net_key.clone(), a.clone(), b.clone(), c.clone()).as_bytes()).as_bytes())
-    };
-
-    #[derive(AsBytes)]
-    #[repr(C)]
-    struct KeyHashData(Hash, PublicKey);
-    Key(hash(KeyHashData(double_hash, *pk).as_bytes()).0)
+    let mut h = hash_parts(&[net_key.as_bytes(), a.as_bytes(), b.as_bytes(), c.as_bytes()]);
+    let mut double_hash = hash(&h.0);
+    wipe(&mut h.0);
+
+    let key = Key(hash_parts(&[&double_hash.0, pk.as_bytes()]).0);
+    wipe(&mut double_hash.0);
+    key
+}
+
+/// Secret from which `HandshakeKeys::export_keying_material` derives its output.
+pub fn exporter_secret(
+    net_key: &NetworkKey,
+    shared_a: &SharedA,
+    shared_b: &SharedB,
+    shared_c: &SharedC,
+) -> [u8; 32] {
+    // sha256("shs exporter" + sha256(net_key + a + b + c))
+    // The box-stream keys are derived from the double hash instead, so neither
+    // can be computed from the other.
+
+    let mut h = hash_parts(&[
+        net_key.as_bytes(),
+        shared_a.as_bytes(),
+        shared_b.as_bytes(),
+        shared_c.as_bytes(),
+    ]);
+    let secret = hash_parts(&[b"shs exporter", &h.0]).0;
+    wipe(&mut h.0);
+    secret
 }
 
 /// Final shared key used to seal and open secret boxes (client to server)
@@ -7,7 +7,7 @@ use ssb_crypto::ephemeral::{
 use ssb_crypto::{hash, Hash, Keypair};
 
 /// Shared Secret A (client and server ephemeral keys)
-#[derive(AsBytes, Clone)]
+#[derive(AsBytes)]
 #[repr(C)]
 pub struct SharedA(SharedSecret);
 impl SharedA {
@@ -35,8 +35,18 @@ impl SharedA {
 #[repr(C)]
 pub(crate) struct SharedAHash(Hash);
 
+wipe_on_drop!(SharedA, SharedB, SharedC, SharedAHash);
+
+/// All three shared secrets, for the message and keys derived from them together.
+#[derive(Copy, Clone)]
+pub struct SharedSecrets<'a> {
+    pub a: &'a SharedA,
+    pub b: &'a SharedB,
+    pub c: &'a SharedC,
+}
+
 /// Shared Secret B (client ephemeral key, server long-term key)
-#[derive(AsBytes, Clone)]
+#[derive(AsBytes)]
 #[repr(C)]
 pub struct SharedB(SharedSecret);
 impl SharedB {
@@ -45,36 +55,60 @@ impl SharedB {
     //   pk_to_curve25519(server_longterm_pk)
     // )
     pub fn client_side(sk: &ClientEphSecretKey, pk: &ServerPublicKey) -> Option<SharedB> {
-        // pk_to_curve(&pk.0)
-        //     .and_then(|c| derive_shared_secret(&sk.0, &c))
         derive_shared_secret_pk(&sk.0, &pk.0).map(SharedB)
     }
 
+    /// As `client_side`, with the server's key already converted to curve25519.
+    pub fn client_side_curve(
+        sk: &ClientEphSecretKey,
+        pk: &ServerCurvePublicKey,
+    ) -> Option<SharedB> {
+        derive_shared_secret(&sk.0, &pk.0).map(SharedB)
+    }
+
     // shared_secret_aB = nacl_scalarmult(
     //   sk_to_curve25519(server_longterm_sk),
     //   client_ephemeral_pk
     // )
     pub fn server_side(kp: &Keypair, pk: &ClientEphPublicKey) -> Option<SharedB> {
-        // sk_to_curve(&sk.0)
-        //     .and_then(|c| derive_shared_secret(&c, &pk.0))
         derive_shared_secret_sk(&kp.secret, &pk.0).map(SharedB)
     }
+
+    /// As `server_side`, with the server's key already converted to curve25519.
+    pub fn server_side_curve(
+        sk: &ServerCurveSecretKey,
+        pk: &ClientEphPublicKey,
+    ) -> Option<SharedB> {
+        derive_shared_secret(&sk.0, &pk.0).map(SharedB)
+    }
 }
 
 /// Shared Secret C (client long-term key, server ephemeral key)
-#[derive(AsBytes, Clone)]
+#[derive(AsBytes)]
 #[repr(C)]
 pub struct SharedC(SharedSecret);
 impl SharedC {
+    // shared_secret_Ab = nacl_scalarmult(
+    //   sk_to_curve25519(client_longterm_sk),
+    //   server_ephemeral_pk
+    // )
     pub fn client_side(kp: &Keypair, pk: &ServerEphPublicKey) -> Option<SharedC> {
-        // sk_to_curve(&sk.0)
-        //     .and_then(|c| derive_shared_secret(&c, &pk.0))
         derive_shared_secret_sk(&kp.secret, &pk.0).map(SharedC)
     }
 
+    /// As `client_side`, with the client's key already converted to curve25519.
+    pub fn client_side_curve(
+        sk: &ClientCurveSecretKey,
+        pk: &ServerEphPublicKey,
+    ) -> Option<SharedC> {
+        derive_shared_secret(&sk.0, &pk.0).map(SharedC)
+    }
+
+    // shared_secret_Ab = nacl_scalarmult(
+    //   server_ephemeral_sk,
+    //   pk_to_curve25519(client_longterm_pk)
+    // )
     pub fn server_side(sk: &ServerEphSecretKey, pk: &ClientPublicKey) -> Option<SharedC> {
-        // pk_to_curve(&pk.0)
-        //     .and_then(|c| derive_shared_secret(&sk.0, &c))
         derive_shared_secret_pk(&sk.0, &pk.0).map(SharedC)
     }
 }
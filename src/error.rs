@@ -1,3 +1,5 @@
+use crate::handshake::Stage;
+use core::convert::Infallible;
 use genio::error::ReadExactError;
 
 #[derive(Debug)]
@@ -15,6 +17,8 @@ pub enum HandshakeError<IoErr> {
     SharedAInvalid,
     SharedBInvalid,
     SharedCInvalid,
+    PeerRejected,
+    Timeout { stage: Stage },
 }
 
 impl<IoErr> From<IoErr> for HandshakeError<IoErr> {
@@ -32,6 +36,31 @@ impl<IoErr> From<ReadExactError<IoErr>> for HandshakeError<IoErr> {
     }
 }
 
+impl HandshakeError<Infallible> {
+    /// Convert an error produced by one of the sans-IO state machines
+    /// (which never does any IO) into a `HandshakeError` with any IO error type.
+    pub fn with_io<IoErr>(self) -> HandshakeError<IoErr> {
+        use HandshakeError::*;
+        match self {
+            Io(e) => match e {},
+            UnexpectedEnd => UnexpectedEnd,
+            ClientHelloDeserializeFailed => ClientHelloDeserializeFailed,
+            ClientHelloVerifyFailed => ClientHelloVerifyFailed,
+            ServerHelloDeserializeFailed => ServerHelloDeserializeFailed,
+            ServerHelloVerifyFailed => ServerHelloVerifyFailed,
+            ClientAuthDeserializeFailed => ClientAuthDeserializeFailed,
+            ClientAuthVerifyFailed => ClientAuthVerifyFailed,
+            ServerAcceptDeserializeFailed => ServerAcceptDeserializeFailed,
+            ServerAcceptVerifyFailed => ServerAcceptVerifyFailed,
+            SharedAInvalid => SharedAInvalid,
+            SharedBInvalid => SharedBInvalid,
+            SharedCInvalid => SharedCInvalid,
+            PeerRejected => PeerRejected,
+            Timeout { stage } => Timeout { stage },
+        }
+    }
+}
+
 #[cfg(feature = "std")]
 impl<IoErr> std::fmt::Display for HandshakeError<IoErr>
 where
@@ -53,6 +82,8 @@ where
             SharedAInvalid => write!(f, "Shared secret A is invalid"),
             SharedBInvalid => write!(f, "Shared secret B is invalid"),
             SharedCInvalid => write!(f, "Shared secret C is invalid"),
+            PeerRejected => write!(f, "Peer was rejected by the server"),
+            Timeout { stage } => write!(f, "Timed out during {}", stage),
         }
     }
 }
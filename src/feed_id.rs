@@ -0,0 +1,71 @@
+//! SSB feed identifiers, eg. `@NNpMLXoHUbSTSMnxlWHM7RqXUHpjfA3EhJ1yVtt1i5U=.ed25519`.
+
+use core::fmt;
+use core::str::FromStr;
+use ssb_crypto::PublicKey;
+
+/// A public key, displayed and parsed in the `@<base64>.ed25519` feed id format.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct FeedId(pub PublicKey);
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParseFeedIdError {
+    /// The id doesn't start with `@`.
+    MissingSigil,
+    /// The id doesn't end with `.ed25519`.
+    UnsupportedAlgorithm,
+    InvalidBase64,
+    /// The decoded key isn't a valid ed25519 public key.
+    InvalidKey,
+}
+
+impl fmt::Display for ParseFeedIdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ParseFeedIdError::*;
+        match self {
+            MissingSigil => write!(f, "Feed id doesn't start with @"),
+            UnsupportedAlgorithm => write!(f, "Feed id doesn't end with .ed25519"),
+            InvalidBase64 => write!(f, "Feed id isn't valid base64"),
+            InvalidKey => write!(f, "Feed id isn't a valid ed25519 public key"),
+        }
+    }
+}
+
+impl std::error::Error for ParseFeedIdError {}
+
+impl FromStr for FeedId {
+    type Err = ParseFeedIdError;
+
+    fn from_str(s: &str) -> Result<FeedId, ParseFeedIdError> {
+        use ParseFeedIdError::*;
+
+        let s = s.strip_prefix('@').ok_or(MissingSigil)?;
+        let s = s.strip_suffix(".ed25519").ok_or(UnsupportedAlgorithm)?;
+        let bytes = base64::decode(s).map_err(|_| InvalidBase64)?;
+        PublicKey::from_slice(&bytes).map(FeedId).ok_or(InvalidKey)
+    }
+}
+
+impl fmt::Display for FeedId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "@{}.ed25519", base64::encode((self.0).0))
+    }
+}
+
+impl fmt::Debug for FeedId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FeedId({})", self)
+    }
+}
+
+impl From<PublicKey> for FeedId {
+    fn from(pk: PublicKey) -> FeedId {
+        FeedId(pk)
+    }
+}
+
+impl From<FeedId> for PublicKey {
+    fn from(id: FeedId) -> PublicKey {
+        id.0
+    }
+}
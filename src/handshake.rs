@@ -0,0 +1,684 @@
+//! Sans-IO handshake state machines.
+//!
+//! [`ClientHandshake`] and [`ServerHandshake`] don't read or write anything themselves.
+//! The caller asks for the next [`Step`], and then either writes the next outgoing
+//! message into a buffer (and sends it to the peer however it likes), or reads
+//! the requested number of bytes from the peer and passes them in.
+//!
+//! The async and [`sync`](crate::sync) `client_side`/`server_side` functions
+//! are thin wrappers around these.
+
+use crate::bytes::{wipe, AsBytes};
+use crate::config::{ClientConfig, ServerConfig};
+use crate::crypto::{hash_parts, keys::*, message::*, outcome::*, shared_secret::*};
+use crate::error::HandshakeError;
+
+use core::convert::Infallible;
+use core::fmt;
+use core::mem::{replace, size_of};
+use core::slice;
+use ssb_crypto::ephemeral::{EphPublicKey, EphSecretKey};
+use ssb_crypto::{hash, Hash, Keypair, NetworkKey, PublicKey};
+
+/// Size in bytes of the largest handshake message (the client auth message).
+pub const MAX_MESSAGE_SIZE: usize = size_of::<ClientAuth>();
+
+/// What a handshake state machine needs the caller to do next.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Step {
+    /// Call `write_message` with a buffer of this many bytes,
+    /// and send the bytes to the peer.
+    Send(usize),
+    /// Read exactly this many bytes from the peer, and pass them to `read_message`.
+    Recv(usize),
+    /// The handshake completed successfully; call `into_keys`.
+    Done,
+}
+
+/// The four messages of the handshake, in order.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Stage {
+    ClientHello,
+    ServerHello,
+    ClientAuth,
+    ServerAccept,
+}
+
+impl Stage {
+    /// All four stages, in order.
+    pub const ALL: [Stage; 4] = [
+        Stage::ClientHello,
+        Stage::ServerHello,
+        Stage::ClientAuth,
+        Stage::ServerAccept,
+    ];
+
+    /// Size in bytes of the message sent at this stage.
+    pub fn message_size(self) -> usize {
+        match self {
+            Stage::ClientHello => size_of::<ClientHello>(),
+            Stage::ServerHello => size_of::<ServerHello>(),
+            Stage::ClientAuth => size_of::<ClientAuth>(),
+            Stage::ServerAccept => size_of::<ServerAccept>(),
+        }
+    }
+
+    /// Which side sends the message at this stage.
+    pub fn sender(self) -> Role {
+        match self {
+            Stage::ClientHello | Stage::ClientAuth => Role::Client,
+            Stage::ServerHello | Stage::ServerAccept => Role::Server,
+        }
+    }
+}
+
+/// Which side of the handshake we're on.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Role {
+    Client,
+    Server,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stage::ClientHello => write!(f, "client hello"),
+            Stage::ServerHello => write!(f, "server hello"),
+            Stage::ClientAuth => write!(f, "client auth"),
+            Stage::ServerAccept => write!(f, "server accept"),
+        }
+    }
+}
+
+/// Running hash of the handshake messages: `h = sha256(h + sha256(message))`,
+/// starting from 32 zero bytes.
+struct TranscriptHash([u8; 32]);
+
+impl TranscriptHash {
+    fn new() -> TranscriptHash {
+        TranscriptHash([0; 32])
+    }
+
+    fn update(&mut self, msg: &[u8]) {
+        self.0 = hash_parts(&[&self.0, &hash(msg).0]).0;
+    }
+}
+
+/// Client side of the handshake, as a sans-IO state machine.
+///
+/// Message order: send `ClientHello`, receive `ServerHello`,
+/// send `ClientAuth`, receive `ServerAccept`.
+pub struct ClientHandshake<'a> {
+    net_key: &'a NetworkKey,
+    keypair: &'a Keypair,
+    server_pk: ServerPublicKey,
+    /// Set if the long-term keys were converted to curve25519 in advance.
+    config: Option<&'a ClientConfig>,
+    eph_pk: ClientEphPublicKey,
+    eph_sk: ClientEphSecretKey,
+    transcript: TranscriptHash,
+    state: ClientState,
+}
+
+struct ClientSecrets {
+    server_eph_pk: ServerEphPublicKey,
+    shared_a: SharedA,
+    shared_b: SharedB,
+    shared_c: SharedC,
+}
+
+impl ClientSecrets {
+    fn shared(&self) -> SharedSecrets<'_> {
+        SharedSecrets {
+            a: &self.shared_a,
+            b: &self.shared_b,
+            c: &self.shared_c,
+        }
+    }
+}
+
+enum ClientState {
+    SendHello,
+    RecvHello,
+    SendAuth(ClientSecrets),
+    RecvAccept(ClientSecrets, ClientProof),
+    Done(HandshakeOutcome),
+    Failed,
+}
+
+impl<'a> ClientHandshake<'a> {
+    pub fn new(
+        net_key: &'a NetworkKey,
+        keypair: &'a Keypair,
+        server_pk: &PublicKey,
+        eph_kp: (EphPublicKey, EphSecretKey),
+    ) -> ClientHandshake<'a> {
+        ClientHandshake {
+            net_key,
+            keypair,
+            server_pk: ServerPublicKey(*server_pk),
+            config: None,
+            eph_pk: ClientEphPublicKey(eph_kp.0),
+            eph_sk: ClientEphSecretKey(eph_kp.1),
+            transcript: TranscriptHash::new(),
+            state: ClientState::SendHello,
+        }
+    }
+
+    /// Like `new`, but using the curve25519 keys computed by the `ClientConfig`,
+    /// instead of converting the long-term keys again.
+    pub fn with_config(
+        config: &'a ClientConfig,
+        eph_kp: (EphPublicKey, EphSecretKey),
+    ) -> ClientHandshake<'a> {
+        ClientHandshake {
+            config: Some(config),
+            ..ClientHandshake::new(&config.net_key, &config.keypair, &config.server_pk, eph_kp)
+        }
+    }
+
+    /// What the caller needs to do next.
+    ///
+    /// # Panics
+    /// If called after `read_message` returned an error.
+    pub fn step(&self) -> Step {
+        use ClientState::*;
+        match &self.state {
+            SendHello => Step::Send(size_of::<ClientHello>()),
+            RecvHello => Step::Recv(size_of::<ServerHello>()),
+            SendAuth(_) => Step::Send(size_of::<ClientAuth>()),
+            RecvAccept(..) => Step::Recv(size_of::<ServerAccept>()),
+            Done(_) => Step::Done,
+            Failed => panic!("ClientHandshake used after failure"),
+        }
+    }
+
+    /// The message that is to be sent or received next, if any.
+    pub fn stage(&self) -> Option<Stage> {
+        use ClientState::*;
+        match &self.state {
+            SendHello => Some(Stage::ClientHello),
+            RecvHello => Some(Stage::ServerHello),
+            SendAuth(_) => Some(Stage::ClientAuth),
+            RecvAccept(..) => Some(Stage::ServerAccept),
+            Done(_) | Failed => None,
+        }
+    }
+
+    /// Write the next outgoing message into `out`, returning the number of bytes written.
+    ///
+    /// # Panics
+    /// If the current step isn't `Step::Send(n)`, or `out.len() != n`.
+    pub fn write_message(&mut self, out: &mut [u8]) -> usize {
+        use ClientState::*;
+        match replace(&mut self.state, Failed) {
+            SendHello => {
+                out.copy_from_slice(ClientHello::new(&self.eph_pk, self.net_key).as_bytes());
+                self.transcript.update(out);
+                self.state = RecvHello;
+            }
+            SendAuth(s) => {
+                let (msg, proof) = ClientAuth::new(
+                    self.keypair,
+                    &self.server_pk,
+                    self.net_key,
+                    &s.shared_a,
+                    &s.shared_b,
+                );
+                out.copy_from_slice(msg.as_bytes());
+                self.transcript.update(out);
+                self.state = RecvAccept(s, proof);
+            }
+            _ => panic!("ClientHandshake::write_message called in wrong state"),
+        }
+        out.len()
+    }
+
+    /// Handle the next incoming message.
+    /// Fails with the relevant `*DeserializeFailed` error if `msg` is the wrong size.
+    /// After an error, the handshake can't be continued.
+    ///
+    /// # Panics
+    /// If the current step isn't `Step::Recv(_)`.
+    pub fn read_message(&mut self, msg: &[u8]) -> Result<(), HandshakeError<Infallible>> {
+        use HandshakeError::*;
+
+        self.transcript.update(msg);
+        match replace(&mut self.state, ClientState::Failed) {
+            ClientState::RecvHello => {
+                let server_eph_pk = ServerHello::from_bytes(msg)?
+                    .verify(self.net_key)
+                    .ok_or(ServerHelloVerifyFailed)?;
+
+                // Derive shared secrets
+                let shared_a =
+                    SharedA::client_side(&self.eph_sk, &server_eph_pk).ok_or(SharedAInvalid)?;
+                let (shared_b, shared_c) = match self.config {
+                    Some(c) => (
+                        SharedB::client_side_curve(&self.eph_sk, &c.server_curve_pk),
+                        SharedC::client_side_curve(&c.curve_sk, &server_eph_pk),
+                    ),
+                    None => (
+                        SharedB::client_side(&self.eph_sk, &self.server_pk),
+                        SharedC::client_side(self.keypair, &server_eph_pk),
+                    ),
+                };
+                let shared_b = shared_b.ok_or(SharedBInvalid)?;
+                let shared_c = shared_c.ok_or(SharedCInvalid)?;
+
+                self.state = ClientState::SendAuth(ClientSecrets {
+                    server_eph_pk,
+                    shared_a,
+                    shared_b,
+                    shared_c,
+                });
+            }
+            ClientState::RecvAccept(s, proof) => {
+                ServerAccept::from_bytes(msg)?
+                    .verify(&proof, &self.server_pk, self.net_key, s.shared())
+                    .ok_or(ServerAcceptVerifyFailed)?;
+
+                self.state = ClientState::Done(self.outcome(&s));
+            }
+            _ => panic!("ClientHandshake::read_message called in wrong state"),
+        }
+        Ok(())
+    }
+
+    /// Returns the resulting keys if the handshake is done, or `None` otherwise.
+    pub fn into_keys(self) -> Option<HandshakeKeys> {
+        self.into_outcome().map(|o| o.keys)
+    }
+
+    /// Returns the full outcome if the handshake is done, or `None` otherwise.
+    pub fn into_outcome(self) -> Option<HandshakeOutcome> {
+        match self.state {
+            ClientState::Done(outcome) => Some(outcome),
+            _ => None,
+        }
+    }
+
+    fn outcome(&self, s: &ClientSecrets) -> HandshakeOutcome {
+        let net_key = self.net_key;
+        let keys = HandshakeKeys {
+            read_key: server_to_client_key(
+                &ClientPublicKey(self.keypair.public),
+                net_key,
+                &s.shared_a,
+                &s.shared_b,
+                &s.shared_c,
+            ),
+            read_starting_nonce: starting_nonce(net_key, &self.eph_pk.0),
+
+            write_key: client_to_server_key(
+                &self.server_pk,
+                net_key,
+                &s.shared_a,
+                &s.shared_b,
+                &s.shared_c,
+            ),
+            write_starting_nonce: starting_nonce(net_key, &s.server_eph_pk.0),
+
+            peer_key: self.server_pk.0,
+            exporter_secret: exporter_secret(net_key, &s.shared_a, &s.shared_b, &s.shared_c),
+        };
+        HandshakeOutcome {
+            keys,
+            role: Role::Client,
+            net_key: net_key.clone(),
+            net_key_index: 0,
+            keypair_index: 0,
+            our_eph_key: self.eph_pk.0,
+            peer_eph_key: s.server_eph_pk.0,
+            transcript_hash: Hash(self.transcript.0),
+        }
+    }
+}
+
+/// Server side of the handshake, as a sans-IO state machine.
+///
+/// Message order: receive `ClientHello`, send `ServerHello`,
+/// receive `ClientAuth`, send `ServerAccept`.
+pub struct ServerHandshake<'a> {
+    net_keys: &'a [NetworkKey],
+    net_key_index: usize,
+    keypairs: &'a [Keypair],
+    keypair_index: usize,
+    /// Set if the (only) keypair was converted to curve25519 in advance.
+    config: Option<&'a ServerConfig>,
+    eph_pk: ServerEphPublicKey,
+    eph_sk: ServerEphSecretKey,
+    transcript: TranscriptHash,
+    state: ServerState,
+}
+
+struct ServerSecrets {
+    client_eph_pk: ClientEphPublicKey,
+    shared_a: SharedA,
+    shared_b: SharedB,
+}
+
+enum ServerState {
+    RecvHello,
+    SendHello(ClientEphPublicKey, SharedA),
+    RecvAuth(ClientEphPublicKey, SharedA),
+    SendAccept(ServerSecrets, ClientProof, SharedC),
+    Done(HandshakeOutcome),
+    Failed,
+}
+
+impl<'a> ServerHandshake<'a> {
+    pub fn new(
+        net_key: &'a NetworkKey,
+        keypair: &'a Keypair,
+        eph_kp: (EphPublicKey, EphSecretKey),
+    ) -> ServerHandshake<'a> {
+        ServerHandshake::with_network_keys(slice::from_ref(net_key), keypair, eph_kp)
+    }
+
+    /// Accept clients on any of the given networks.
+    /// The network is chosen by whichever key the client's `ClientHello` verifies with;
+    /// see [`network_key_index`](ServerHandshake::network_key_index).
+    pub fn with_network_keys(
+        net_keys: &'a [NetworkKey],
+        keypair: &'a Keypair,
+        eph_kp: (EphPublicKey, EphSecretKey),
+    ) -> ServerHandshake<'a> {
+        ServerHandshake::with_keypairs(net_keys, slice::from_ref(keypair), eph_kp)
+    }
+
+    /// Accept clients on any of the given networks, answering as any of the given
+    /// long-term identities.
+    /// The identity is chosen by whichever keypair the client's `ClientAuth` message
+    /// decrypts with; see [`keypair_index`](ServerHandshake::keypair_index).
+    pub fn with_keypairs(
+        net_keys: &'a [NetworkKey],
+        keypairs: &'a [Keypair],
+        eph_kp: (EphPublicKey, EphSecretKey),
+    ) -> ServerHandshake<'a> {
+        ServerHandshake {
+            net_keys,
+            net_key_index: 0,
+            keypairs,
+            keypair_index: 0,
+            config: None,
+            eph_pk: ServerEphPublicKey(eph_kp.0),
+            eph_sk: ServerEphSecretKey(eph_kp.1),
+            transcript: TranscriptHash::new(),
+            state: ServerState::RecvHello,
+        }
+    }
+
+    /// Like `new`, but using the curve25519 key computed by the `ServerConfig`,
+    /// instead of converting the long-term secret key again.
+    pub fn with_config(
+        config: &'a ServerConfig,
+        eph_kp: (EphPublicKey, EphSecretKey),
+    ) -> ServerHandshake<'a> {
+        ServerHandshake {
+            config: Some(config),
+            ..ServerHandshake::new(&config.net_key, &config.keypair, eph_kp)
+        }
+    }
+
+    /// What the caller needs to do next.
+    ///
+    /// # Panics
+    /// If called after `read_message` returned an error.
+    pub fn step(&self) -> Step {
+        use ServerState::*;
+        match &self.state {
+            RecvHello => Step::Recv(size_of::<ClientHello>()),
+            SendHello(..) => Step::Send(size_of::<ServerHello>()),
+            RecvAuth(..) => Step::Recv(size_of::<ClientAuth>()),
+            SendAccept(..) => Step::Send(size_of::<ServerAccept>()),
+            Done(_) => Step::Done,
+            Failed => panic!("ServerHandshake used after failure"),
+        }
+    }
+
+    /// The message that is to be sent or received next, if any.
+    pub fn stage(&self) -> Option<Stage> {
+        use ServerState::*;
+        match &self.state {
+            RecvHello => Some(Stage::ClientHello),
+            SendHello(..) => Some(Stage::ServerHello),
+            RecvAuth(..) => Some(Stage::ClientAuth),
+            SendAccept(..) => Some(Stage::ServerAccept),
+            Done(_) | Failed => None,
+        }
+    }
+
+    /// Write the next outgoing message into `out`, returning the number of bytes written.
+    ///
+    /// # Panics
+    /// If the current step isn't `Step::Send(n)`, or `out.len() != n`.
+    pub fn write_message(&mut self, out: &mut [u8]) -> usize {
+        use ServerState::*;
+        match replace(&mut self.state, Failed) {
+            SendHello(client_eph_pk, shared_a) => {
+                out.copy_from_slice(ServerHello::new(&self.eph_pk, self.net_key()).as_bytes());
+                self.transcript.update(out);
+                self.state = RecvAuth(client_eph_pk, shared_a);
+            }
+            SendAccept(s, proof, shared_c) => {
+                let secrets = SharedSecrets {
+                    a: &s.shared_a,
+                    b: &s.shared_b,
+                    c: &shared_c,
+                };
+                let msg = ServerAccept::new(self.keypair(), &proof, self.net_key(), secrets);
+                out.copy_from_slice(msg.as_bytes());
+                self.transcript.update(out);
+                self.state = Done(self.outcome(&s, &proof.pk, &shared_c));
+            }
+            _ => panic!("ServerHandshake::write_message called in wrong state"),
+        }
+        out.len()
+    }
+
+    /// Handle the next incoming message.
+    /// Fails with the relevant `*DeserializeFailed` error if `msg` is the wrong size.
+    /// After an error, the handshake can't be continued.
+    ///
+    /// # Panics
+    /// If the current step isn't `Step::Recv(_)`.
+    pub fn read_message(&mut self, msg: &[u8]) -> Result<(), HandshakeError<Infallible>> {
+        use HandshakeError::*;
+
+        self.transcript.update(msg);
+        match replace(&mut self.state, ServerState::Failed) {
+            ServerState::RecvHello => {
+                let hello = ClientHello::from_bytes(msg)?;
+                let (index, client_eph_pk) = self
+                    .net_keys
+                    .iter()
+                    .enumerate()
+                    .find_map(|(i, k)| hello.verify(k).map(|pk| (i, pk)))
+                    .ok_or(ClientHelloVerifyFailed)?;
+                self.net_key_index = index;
+
+                // Derive shared secret
+                let shared_a =
+                    SharedA::server_side(&self.eph_sk, &client_eph_pk).ok_or(SharedAInvalid)?;
+
+                self.state = ServerState::SendHello(client_eph_pk, shared_a);
+            }
+            ServerState::RecvAuth(client_eph_pk, shared_a) => {
+                if msg.len() != size_of::<ClientAuth>() {
+                    return Err(ClientAuthDeserializeFailed);
+                }
+
+                // Shared secret B depends on which of our identities the client
+                // is connecting to, and the auth message only decrypts under the right one.
+                let mut verified = None;
+                for (i, keypair) in self.keypairs.iter().enumerate() {
+                    let shared_b = match self.config {
+                        Some(c) => SharedB::server_side_curve(&c.curve_sk, &client_eph_pk),
+                        None => SharedB::server_side(keypair, &client_eph_pk),
+                    }
+                    .ok_or(SharedBInvalid)?;
+
+                    // Copy the message, as it's decrypted in place.
+                    let mut buf = [0u8; size_of::<ClientAuth>()];
+                    buf.copy_from_slice(msg);
+                    let v = ClientAuth::from_bytes(&mut buf)?.verify(
+                        keypair,
+                        self.net_key(),
+                        &shared_a,
+                        &shared_b,
+                    );
+                    wipe(&mut buf);
+                    if let Some(v) = v {
+                        verified = Some((i, shared_b, v));
+                        break;
+                    }
+                }
+                let (index, shared_b, proof) = verified.ok_or(ClientAuthVerifyFailed)?;
+                self.keypair_index = index;
+
+                // Derive shared secret
+                let shared_c =
+                    SharedC::server_side(&self.eph_sk, &proof.pk).ok_or(SharedCInvalid)?;
+
+                let s = ServerSecrets {
+                    client_eph_pk,
+                    shared_a,
+                    shared_b,
+                };
+                self.state = ServerState::SendAccept(s, proof, shared_c);
+            }
+            _ => panic!("ServerHandshake::read_message called in wrong state"),
+        }
+        Ok(())
+    }
+
+    /// The client's long-term public key, once its `ClientAuth` message has been verified.
+    ///
+    /// This is available before `ServerAccept` is sent, so the server can decide
+    /// whether to let the client in. To reject the client, simply stop the handshake
+    /// (and close the connection) instead of sending the next message.
+    pub fn client_public_key(&self) -> Option<PublicKey> {
+        match &self.state {
+            ServerState::SendAccept(_, proof, _) => Some(proof.pk.0),
+            ServerState::Done(outcome) => Some(outcome.keys.peer_key),
+            _ => None,
+        }
+    }
+
+    /// The index (into the keys given to `with_network_keys`) of the network
+    /// the client is connecting to, once its `ClientHello` message has been verified.
+    pub fn network_key_index(&self) -> Option<usize> {
+        match &self.state {
+            ServerState::RecvHello | ServerState::Failed => None,
+            _ => Some(self.net_key_index),
+        }
+    }
+
+    /// The index (into the keypairs given to `with_keypairs`) of the identity
+    /// the client is connecting to, once its `ClientAuth` message has been verified.
+    pub fn keypair_index(&self) -> Option<usize> {
+        match &self.state {
+            ServerState::SendAccept(..) | ServerState::Done(_) => Some(self.keypair_index),
+            _ => None,
+        }
+    }
+
+    /// Returns the resulting keys if the handshake is done, or `None` otherwise.
+    pub fn into_keys(self) -> Option<HandshakeKeys> {
+        self.into_outcome().map(|o| o.keys)
+    }
+
+    /// Returns the full outcome if the handshake is done, or `None` otherwise.
+    pub fn into_outcome(self) -> Option<HandshakeOutcome> {
+        match self.state {
+            ServerState::Done(outcome) => Some(outcome),
+            _ => None,
+        }
+    }
+
+    fn net_key(&self) -> &'a NetworkKey {
+        &self.net_keys[self.net_key_index]
+    }
+
+    fn keypair(&self) -> &'a Keypair {
+        &self.keypairs[self.keypair_index]
+    }
+
+    fn outcome(
+        &self,
+        s: &ServerSecrets,
+        client_pk: &ClientPublicKey,
+        shared_c: &SharedC,
+    ) -> HandshakeOutcome {
+        let net_key = self.net_key();
+        let keys = HandshakeKeys {
+            read_key: client_to_server_key(
+                &ServerPublicKey(self.keypair().public),
+                net_key,
+                &s.shared_a,
+                &s.shared_b,
+                shared_c,
+            ),
+            read_starting_nonce: starting_nonce(net_key, &self.eph_pk.0),
+
+            write_key: server_to_client_key(client_pk, net_key, &s.shared_a, &s.shared_b, shared_c),
+            write_starting_nonce: starting_nonce(net_key, &s.client_eph_pk.0),
+
+            peer_key: client_pk.0,
+            exporter_secret: exporter_secret(net_key, &s.shared_a, &s.shared_b, shared_c),
+        };
+        HandshakeOutcome {
+            keys,
+            role: Role::Server,
+            net_key: net_key.clone(),
+            net_key_index: self.net_key_index,
+            keypair_index: self.keypair_index,
+            our_eph_key: self.eph_pk.0,
+            peer_eph_key: s.client_eph_pk.0,
+            transcript_hash: Hash(self.transcript.0),
+        }
+    }
+}
+
+/// Common interface to the two state machines, used by the IO wrappers.
+pub(crate) trait Handshake {
+    fn step(&self) -> Step;
+    /// Only needed for the timeouts, which are std-only.
+    #[cfg(feature = "std")]
+    fn stage(&self) -> Option<Stage>;
+    fn write_message(&mut self, out: &mut [u8]) -> usize;
+    fn read_message(&mut self, msg: &[u8]) -> Result<(), HandshakeError<Infallible>>;
+}
+
+impl Handshake for ClientHandshake<'_> {
+    fn step(&self) -> Step {
+        ClientHandshake::step(self)
+    }
+    #[cfg(feature = "std")]
+    fn stage(&self) -> Option<Stage> {
+        ClientHandshake::stage(self)
+    }
+    fn write_message(&mut self, out: &mut [u8]) -> usize {
+        ClientHandshake::write_message(self, out)
+    }
+    fn read_message(&mut self, msg: &[u8]) -> Result<(), HandshakeError<Infallible>> {
+        ClientHandshake::read_message(self, msg)
+    }
+}
+
+impl Handshake for ServerHandshake<'_> {
+    fn step(&self) -> Step {
+        ServerHandshake::step(self)
+    }
+    #[cfg(feature = "std")]
+    fn stage(&self) -> Option<Stage> {
+        ServerHandshake::stage(self)
+    }
+    fn write_message(&mut self, out: &mut [u8]) -> usize {
+        ServerHandshake::write_message(self, out)
+    }
+    fn read_message(&mut self, msg: &[u8]) -> Result<(), HandshakeError<Infallible>> {
+        ServerHandshake::read_message(self, msg)
+    }
+}
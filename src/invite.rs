@@ -0,0 +1,138 @@
+//! Pub invite codes, of the form `host:port:@<pub key>.ed25519~<base64 seed>`.
+//!
+//! An invite is redeemed by doing a handshake with the pub, as the keypair derived
+//! from the invite's seed.
+
+use crate::FeedId;
+
+use core::fmt;
+use core::str::FromStr;
+use ssb_crypto::{Keypair, PublicKey};
+
+/// An invite code. The seed is the secret part; it's left out of the `Debug` output,
+/// but included in the `Display` output (the invite code itself).
+#[derive(Clone, PartialEq, Eq)]
+pub struct Invite {
+    pub host: String,
+    pub port: u16,
+    /// The pub's public key.
+    pub key: PublicKey,
+    seed: [u8; 32],
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParseInviteError {
+    /// The invite doesn't have the form `host:port:@key.ed25519~seed`.
+    Malformed,
+    InvalidPort,
+    InvalidKey,
+    InvalidSeed,
+}
+
+impl fmt::Display for ParseInviteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ParseInviteError::*;
+        match self {
+            Malformed => write!(f, "Malformed invite code"),
+            InvalidPort => write!(f, "Invalid port in invite code"),
+            InvalidKey => write!(f, "Invalid pub key in invite code"),
+            InvalidSeed => write!(f, "Invalid seed in invite code"),
+        }
+    }
+}
+
+impl std::error::Error for ParseInviteError {}
+
+impl Invite {
+    pub fn new(host: String, port: u16, key: PublicKey, seed: [u8; 32]) -> Invite {
+        Invite {
+            host,
+            port,
+            key,
+            seed,
+        }
+    }
+
+    /// The secret seed from which the invite's keypair is derived.
+    pub fn seed(&self) -> &[u8; 32] {
+        &self.seed
+    }
+
+    /// The keypair to do the handshake with when redeeming the invite.
+    pub fn keypair(&self) -> Keypair {
+        // Any 32 bytes make a valid ed25519 seed.
+        Keypair::from_seed(&self.seed).unwrap()
+    }
+}
+
+impl FromStr for Invite {
+    type Err = ParseInviteError;
+
+    fn from_str(s: &str) -> Result<Invite, ParseInviteError> {
+        use ParseInviteError::*;
+
+        let mut parts = s.trim().splitn(2, '~');
+        let addr = parts.next().unwrap_or("");
+        let seed = parts.next().ok_or(Malformed)?;
+
+        // The host may be an ipv6 address, so split from the right.
+        let mut addr = addr.rsplitn(3, ':');
+        let key = addr.next().ok_or(Malformed)?;
+        let port = addr.next().ok_or(Malformed)?;
+        let host = addr.next().filter(|h| !h.is_empty()).ok_or(Malformed)?;
+
+        let port = port.parse().map_err(|_| InvalidPort)?;
+        let key = key.parse::<FeedId>().map_err(|_| InvalidKey)?.0;
+
+        let seed = base64::decode(seed).map_err(|_| InvalidSeed)?;
+        if seed.len() != 32 {
+            return Err(InvalidSeed);
+        }
+        let mut s = [0; 32];
+        s.copy_from_slice(&seed);
+
+        Ok(Invite {
+            host: host.to_string(),
+            port,
+            key,
+            seed: s,
+        })
+    }
+}
+
+impl fmt::Debug for Invite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Invite")
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("key", &FeedId(self.key))
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for Invite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}~{}",
+            self.host,
+            self.port,
+            FeedId(self.key),
+            base64::encode(self.seed)
+        )
+    }
+}
+
+/// Redeem the invite, by connecting to the pub and performing the client side
+/// of the handshake as the invite's keypair.
+/// Returns the stream, over which the pub expects the `invite.use` request.
+#[cfg(all(feature = "net", feature = "getrandom"))]
+pub async fn redeem(
+    invite: &Invite,
+    net_key: &ssb_crypto::NetworkKey,
+) -> Result<(::tokio::net::TcpStream, crate::HandshakeOutcome), crate::HandshakeError<std::io::Error>>
+{
+    let keypair = invite.keypair();
+    let addrs = crate::multiserver::resolve(&invite.host, invite.port).await?;
+    crate::net::connect(&addrs[..], net_key, &keypair, &invite.key).await
+}
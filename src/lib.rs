@@ -2,39 +2,92 @@
 //! [Scuttlebutt Protocol Guide](https://ssbc.github.io/scuttlebutt-protocol-guide/)
 //! ([repo](https://github.com/ssbc/scuttlebutt-protocol-guide)),
 //! which he graciously released into the public domain.
+//!
+//! With the `zeroize` feature (on by default), ephemeral secrets, shared secrets
+//! and session keys held by this crate are wiped from memory when dropped.
 #![cfg_attr(not(feature = "std"), no_std)]
 
+#[macro_use]
 mod bytes;
 mod error;
 pub use error::HandshakeError;
 mod crypto;
+pub use crypto::message::{ClientAuth, ClientHello, ServerAccept, ServerHello};
+pub use crypto::outcome::{HandshakeKeys, HandshakeOutcome};
+mod config;
+pub use config::{ClientConfig, ServerConfig};
+mod options;
+pub use options::{AllowAll, Authorize, AuthorizeAsync, ClientOptions, ServerOptions};
+mod handshake;
+pub use handshake::{ClientHandshake, Role, ServerHandshake, Stage, Step, MAX_MESSAGE_SIZE};
 
 #[cfg(feature = "std")]
 mod util;
+#[cfg(feature = "std")]
+pub use util::Timeouts;
+#[cfg(feature = "std")]
+mod feed_id;
+#[cfg(feature = "std")]
+pub use feed_id::{FeedId, ParseFeedIdError};
 
 #[cfg(feature = "std")]
 #[path = ""]
 mod std_stuff {
     mod client;
+    #[cfg(feature = "getrandom")]
     pub use client::client_side;
+    pub use client::client_side_with;
     mod server;
+    #[cfg(feature = "getrandom")]
     pub use server::server_side;
+    pub use server::server_side_with;
 }
 #[cfg(feature = "std")]
 pub use std_stuff::*;
 
 pub mod sync;
 
-#[cfg(all(test, feature = "std"))]
+#[cfg(feature = "boxstream")]
+pub mod boxstream;
+
+#[cfg(feature = "transcript")]
+pub mod transcript;
+
+#[cfg(all(feature = "tokio", feature = "getrandom"))]
+pub mod tokio_io;
+
+#[cfg(all(feature = "net", feature = "getrandom"))]
+pub mod net;
+
+#[cfg(feature = "multiserver")]
+pub mod multiserver;
+
+#[cfg(feature = "secret-file")]
+pub mod secret_file;
+
+#[cfg(feature = "invite")]
+pub mod invite;
+
+#[cfg(all(feature = "proxy", feature = "getrandom"))]
+pub mod proxy;
+
+#[cfg(all(feature = "std", any(test, feature = "testing")))]
+pub mod testing;
+
+#[cfg(all(test, feature = "std", feature = "getrandom"))]
 mod tests {
     use super::*;
+    use crate::testing::{client_eph_keypair, eph_keypair, server_eph_keypair, Keys, ReplayStream};
     use std::io::ErrorKind;
+    use std::time::Duration;
 
     use futures::executor::block_on;
     use futures::future::join;
 
     extern crate async_ringbuffer;
     use async_ringbuffer::Duplex;
+    use proptest::prelude::*;
+    use ssb_crypto::ephemeral::generate_ephemeral_keypair;
     use ssb_crypto::{Keypair, NetworkKey, PublicKey};
 
     #[test]
@@ -52,6 +105,13 @@ mod tests {
         let c_out = c_out.unwrap();
         let s_out = s_out.unwrap();
 
+        assert_eq!(c_out.role, Role::Client);
+        assert_eq!(s_out.role, Role::Server);
+        assert_eq!(c_out.our_eph_key.0, s_out.peer_eph_key.0);
+        assert_eq!(c_out.peer_eph_key.0, s_out.our_eph_key.0);
+        assert_eq!(c_out.transcript_hash.0, s_out.transcript_hash.0);
+
+        let (c_out, s_out) = (c_out.keys, s_out.keys);
         assert_eq!(c_out.write_key.0, s_out.read_key.0);
         assert_eq!(c_out.read_key.0, s_out.write_key.0);
 
@@ -59,6 +119,880 @@ mod tests {
         assert_eq!(c_out.read_starting_nonce.0, s_out.write_starting_nonce.0);
     }
 
+    #[test]
+    fn sans_io() {
+        let skey = Keypair::generate();
+        let ckey = Keypair::generate();
+        let net_key = NetworkKey::SSB_MAIN_NET;
+
+        let ex = testing::exchange(
+            ClientHandshake::new(&net_key, &ckey, &skey.public, generate_ephemeral_keypair()),
+            ServerHandshake::new(&net_key, &skey, generate_ephemeral_keypair()),
+        );
+
+        let (c_out, s_out) = (ex.client.keys, ex.server.keys);
+        assert_eq!(c_out.write_key.0, s_out.read_key.0);
+        assert_eq!(c_out.read_key.0, s_out.write_key.0);
+        assert_eq!(c_out.peer_key, skey.public);
+        assert_eq!(s_out.peer_key, ckey.public);
+    }
+
+    #[test]
+    fn exported_keying_material_matches() {
+        let (mut c_stream, mut s_stream) = Duplex::pair(1024);
+        let skey = Keypair::generate();
+        let ckey = Keypair::generate();
+
+        let net_key = NetworkKey::SSB_MAIN_NET;
+        let client = client_side(&mut c_stream, &net_key, &ckey, &skey.public);
+        let server = server_side(&mut s_stream, &net_key, &skey);
+        let (c_out, s_out) = block_on(async { join(client, server).await });
+        let (c_out, s_out) = (c_out.unwrap().keys, s_out.unwrap().keys);
+
+        let mut c_ekm = [0; 50];
+        let mut s_ekm = [0; 50];
+        c_out.export_keying_material(b"token", b"ctx", &mut c_ekm);
+        s_out.export_keying_material(b"token", b"ctx", &mut s_ekm);
+        assert_eq!(&c_ekm[..], &s_ekm[..]);
+        assert_ne!(&c_ekm[..32], &c_out.write_key.0[..]);
+        assert_ne!(&c_ekm[..32], &c_out.read_key.0[..]);
+
+        let mut other = [0; 50];
+        c_out.export_keying_material(b"token", b"other", &mut other);
+        assert_ne!(&c_ekm[..], &other[..]);
+        c_out.export_keying_material(b"other", b"ctx", &mut other);
+        assert_ne!(&c_ekm[..], &other[..]);
+    }
+
+    #[test]
+    fn handshake_keys_are_redacted() {
+        use ssb_crypto::secretbox::{Key, Nonce};
+
+        let peer = Keypair::generate().public;
+        let keys = HandshakeKeys::new(
+            Key([1; 32]),
+            Nonce([2; 24]),
+            Key([3; 32]),
+            Nonce([4; 24]),
+            peer,
+            [5; 32],
+        );
+        let id = FeedId(peer).to_string();
+        assert_eq!(keys.to_string(), format!("keys for {} (redacted)", id));
+        assert_eq!(
+            format!("{:?}", keys),
+            format!("HandshakeKeys {{ peer_key: {:?} }}", FeedId(peer))
+        );
+
+        let mut a = [0; 32];
+        let mut b = [0; 32];
+        keys.export_keying_material(b"token", b"", &mut a);
+        HandshakeKeys::new(
+            Key([1; 32]),
+            Nonce([2; 24]),
+            Key([3; 32]),
+            Nonce([4; 24]),
+            peer,
+            [6; 32],
+        )
+        .export_keying_material(b"token", b"", &mut b);
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn server_rejects_unauthorized_client() {
+        let (mut c_stream, mut s_stream) = Duplex::pair(1024);
+        let skey = Keypair::generate();
+        let ckey = Keypair::generate();
+        let blocked = ckey.public;
+
+        let net_key = NetworkKey::SSB_MAIN_NET;
+        let client = client_side(&mut c_stream, &net_key, &ckey, &skey.public);
+        let options = ServerOptions::new(&net_key, &skey)
+            .authorize(|pk: &PublicKey| futures::future::ready(*pk != blocked));
+        let server = server_side_with(&mut s_stream, options);
+
+        let (c_out, s_out) = block_on(async { join(client, server).await });
+
+        assert!(is_eof_err(&c_out));
+        match s_out {
+            Err(HandshakeError::PeerRejected) => {}
+            _ => panic!(),
+        };
+    }
+
+    #[test]
+    fn sync_server_rejects_unauthorized_client() {
+        struct Allowlist(Vec<PublicKey>);
+        impl Authorize for Allowlist {
+            fn authorize(self, pk: &PublicKey) -> bool {
+                self.0.contains(pk)
+            }
+        }
+
+        let skey = Keypair::generate();
+        let ckey = Keypair::generate();
+        let net_key = NetworkKey::SSB_MAIN_NET;
+
+        let ex = testing::exchange(
+            ClientHandshake::new(&net_key, &ckey, &skey.public, client_eph_keypair()),
+            ServerHandshake::new(&net_key, &skey, server_eph_keypair()),
+        );
+        let options = ServerOptions::new(&net_key, &skey)
+            .eph_keypair(server_eph_keypair())
+            .authorize_sync(Allowlist(vec![Keypair::generate().public]));
+        match sync::server_side_with(ReplayStream::new(&ex.c2s), options) {
+            Err(HandshakeError::PeerRejected) => {}
+            _ => panic!(),
+        };
+    }
+
+    #[test]
+    fn client_times_out_on_silent_server() {
+        let (mut c_stream, _s_stream) = Duplex::pair(1024);
+        let skey = Keypair::generate();
+        let ckey = Keypair::generate();
+
+        let net_key = NetworkKey::SSB_MAIN_NET;
+        let timeouts = Timeouts {
+            handshake: None,
+            message: Some(Duration::from_millis(50)),
+        };
+        let options = ClientOptions::new(&net_key, &ckey, &skey.public).timeouts(timeouts);
+        let r = block_on(client_side_with(&mut c_stream, options));
+        match r {
+            Err(HandshakeError::Timeout {
+                stage: Stage::ServerHello,
+            }) => {}
+            _ => panic!(),
+        };
+    }
+
+    #[test]
+    fn deterministic_with_rng() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let skey = Keypair::generate();
+        let ckey = Keypair::generate();
+        let net_key = NetworkKey::SSB_MAIN_NET;
+
+        let run = || {
+            let (mut c_stream, mut s_stream) = Duplex::pair(1024);
+            let mut c_rng = StdRng::seed_from_u64(1);
+            let mut s_rng = StdRng::seed_from_u64(2);
+
+            let client = client_side_with(
+                &mut c_stream,
+                ClientOptions::new(&net_key, &ckey, &skey.public).rng(&mut c_rng),
+            );
+            let server = server_side_with(
+                &mut s_stream,
+                ServerOptions::new(&net_key, &skey).rng(&mut s_rng),
+            );
+            let (c_out, s_out) = block_on(async { join(client, server).await });
+            (c_out.unwrap().keys, s_out.unwrap().keys)
+        };
+
+        let (c1, s1) = run();
+        let (c2, s2) = run();
+        assert_eq!(c1.write_key.0, c2.write_key.0);
+        assert_eq!(c1.read_starting_nonce.0, c2.read_starting_nonce.0);
+        assert_eq!(s1.write_key.0, s2.write_key.0);
+        assert_eq!(s1.read_starting_nonce.0, s2.read_starting_nonce.0);
+    }
+
+    #[test]
+    fn server_with_multiple_network_keys() {
+        let skey = Keypair::generate();
+        let ckey = Keypair::generate();
+        let net_keys = [NetworkKey::SSB_MAIN_NET, NetworkKey::generate()];
+
+        for (i, net_key) in net_keys.iter().enumerate() {
+            let (mut c_stream, mut s_stream) = Duplex::pair(1024);
+            let client = client_side(&mut c_stream, net_key, &ckey, &skey.public);
+            let options = ServerOptions::with_network_keys(&net_keys, &skey);
+            let server = server_side_with(&mut s_stream, options);
+            let (c_out, s_out) = block_on(async { join(client, server).await });
+
+            let c_out = c_out.unwrap().keys;
+            let s_out = s_out.unwrap();
+            assert_eq!(s_out.net_key_index, i);
+            assert_eq!(c_out.write_key.0, s_out.keys.read_key.0);
+        }
+
+        let (mut c_stream, mut s_stream) = Duplex::pair(1024);
+        let other_net = NetworkKey::generate();
+        let client = client_side(&mut c_stream, &other_net, &ckey, &skey.public);
+        let options = ServerOptions::with_network_keys(&net_keys, &skey);
+        let server = server_side_with(&mut s_stream, options);
+        let (c_out, s_out) = block_on(async { join(client, server).await });
+        assert!(c_out.is_err());
+        match s_out {
+            Err(HandshakeError::ClientHelloVerifyFailed) => {}
+            _ => panic!(),
+        };
+    }
+
+    #[test]
+    fn server_with_multiple_keypairs() {
+        let skeys = [Keypair::generate(), Keypair::generate()];
+        let ckey = Keypair::generate();
+        let net_key = NetworkKey::SSB_MAIN_NET;
+
+        for (i, skey) in skeys.iter().enumerate() {
+            let (mut c_stream, mut s_stream) = Duplex::pair(1024);
+            let client = client_side(&mut c_stream, &net_key, &ckey, &skey.public);
+            let options = ServerOptions::with_keypairs(core::slice::from_ref(&net_key), &skeys);
+            let server = server_side_with(&mut s_stream, options);
+            let (c_out, s_out) = block_on(async { join(client, server).await });
+
+            let c_out = c_out.unwrap().keys;
+            let s_out = s_out.unwrap();
+            assert_eq!(s_out.keypair_index, i);
+            assert_eq!(c_out.write_key.0, s_out.keys.read_key.0);
+            assert_eq!(c_out.peer_key, skey.public);
+        }
+
+        let (mut c_stream, mut s_stream) = Duplex::pair(1024);
+        let other = Keypair::generate();
+        let client = client_side(&mut c_stream, &net_key, &ckey, &other.public);
+        let options = ServerOptions::with_keypairs(core::slice::from_ref(&net_key), &skeys);
+        let server = server_side_with(&mut s_stream, options);
+        let (c_out, s_out) = block_on(async { join(client, server).await });
+        assert!(c_out.is_err());
+        match s_out {
+            Err(HandshakeError::ClientAuthVerifyFailed) => {}
+            _ => panic!(),
+        };
+    }
+
+    #[test]
+    fn configs() {
+        let net_key = NetworkKey::SSB_MAIN_NET;
+        let server = ServerConfig::new(net_key.clone(), Keypair::generate()).unwrap();
+        let spk = server.keypair().public;
+        let client = ClientConfig::new(net_key.clone(), Keypair::generate(), spk).unwrap();
+        let cpk = client.keypair().public;
+
+        // A configured side works with an unconfigured peer.
+        let (mut c_stream, mut s_stream) = Duplex::pair(1024);
+        let c = client_side_with(&mut c_stream, ClientOptions::with_config(&client));
+        let s = server_side(&mut s_stream, &net_key, server.keypair());
+        let (c_out, s_out) = block_on(async { join(c, s).await });
+        let (c_out, s_out) = (c_out.unwrap().keys, s_out.unwrap().keys);
+        assert_eq!(c_out.write_key.0, s_out.read_key.0);
+        assert_eq!(c_out.read_key.0, s_out.write_key.0);
+        assert_eq!(s_out.peer_key, cpk);
+
+        let (mut c_stream, mut s_stream) = Duplex::pair(1024);
+        let c = client_side(&mut c_stream, &net_key, client.keypair(), &spk);
+        let s = server_side_with(&mut s_stream, ServerOptions::with_config(&server));
+        let (c_out, s_out) = block_on(async { join(c, s).await });
+        let (c_out, s_out) = (c_out.unwrap().keys, s_out.unwrap().keys);
+        assert_eq!(c_out.write_key.0, s_out.read_key.0);
+        assert_eq!(c_out.peer_key, spk);
+
+        // With the same ephemeral keys, the results are the same as without configs.
+        let configured = testing::exchange(
+            ClientHandshake::with_config(&client, client_eph_keypair()),
+            ServerHandshake::with_config(&server, server_eph_keypair()),
+        );
+        let plain = testing::exchange(
+            ClientHandshake::new(&net_key, client.keypair(), &spk, client_eph_keypair()),
+            ServerHandshake::new(&net_key, server.keypair(), server_eph_keypair()),
+        );
+        let sides = [
+            (&configured.client, &plain.client),
+            (&configured.server, &plain.server),
+        ];
+        for (c, p) in sides.iter() {
+            assert_eq!(c.keys.write_key.0, p.keys.write_key.0);
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    #[::tokio::test]
+    async fn tokio_streams() {
+        use ::tokio::io::{duplex, AsyncReadExt};
+
+        let skey = Keypair::generate();
+        let ckey = Keypair::generate();
+        let net_key = NetworkKey::SSB_MAIN_NET;
+
+        let (mut c_stream, mut s_stream) = duplex(1024);
+        let client = crate::tokio_io::client_side(&mut c_stream, &net_key, &ckey, &skey.public);
+        let server = crate::tokio_io::server_side(&mut s_stream, &net_key, &skey);
+        let (c_out, s_out) = join(client, server).await;
+        let (c_out, s_out) = (c_out.unwrap().keys, s_out.unwrap().keys);
+        assert_eq!(c_out.write_key.0, s_out.read_key.0);
+        assert_eq!(c_out.read_key.0, s_out.write_key.0);
+
+        // The server shuts down its stream when the client is using the wrong network key.
+        let (mut c_stream, mut s_stream) = duplex(1024);
+        let other_net = NetworkKey::generate();
+        let client = crate::tokio_io::client_side(&mut c_stream, &other_net, &ckey, &skey.public);
+        let server = crate::tokio_io::server_side(&mut s_stream, &net_key, &skey);
+        let (c_out, s_out) = join(client, server).await;
+        assert!(c_out.is_err());
+        assert!(s_out.is_err());
+        let mut buf = [0; 1];
+        assert_eq!(c_stream.read(&mut buf).await.unwrap(), 0);
+    }
+
+    #[cfg(feature = "net")]
+    #[::tokio::test]
+    async fn tcp_connect_and_accept() {
+        use crate::net::{connect, ListenerOptions, ShsListener};
+
+        let skey = Keypair::generate();
+        let spk = skey.public;
+        let ckey = Keypair::generate();
+        let net_key = NetworkKey::SSB_MAIN_NET;
+
+        let config = ServerConfig::new(net_key.clone(), skey).unwrap();
+        let mut listener = ShsListener::bind("127.0.0.1:0", ListenerOptions::new(config))
+            .await
+            .unwrap();
+        let addr = listener.local_addr();
+
+        // A peer that never sends anything doesn't block the next one.
+        let _silent = ::tokio::net::TcpStream::connect(addr).await.unwrap();
+
+        let (c_out, s_out) = join(connect(addr, &net_key, &ckey, &spk), listener.accept()).await;
+        let (c_stream, c_out) = c_out.unwrap();
+        let (_, s_out, peer_addr) = s_out.unwrap();
+        assert_eq!(peer_addr, c_stream.local_addr().unwrap());
+        assert_eq!(s_out.keys.peer_key, ckey.public);
+        assert_eq!(c_out.keys.write_key.0, s_out.keys.read_key.0);
+    }
+
+    #[cfg(feature = "net")]
+    #[::tokio::test]
+    async fn tcp_accept_reports_failed_handshakes() {
+        use crate::net::{AcceptError, ListenerOptions, ShsListener};
+
+        let skey = Keypair::generate();
+        let ckey = Keypair::generate();
+        let wrong_pk = Keypair::generate().public;
+        let net_key = NetworkKey::SSB_MAIN_NET;
+
+        let config = ServerConfig::new(net_key.clone(), skey).unwrap();
+        let mut listener = ShsListener::bind("127.0.0.1:0", ListenerOptions::new(config))
+            .await
+            .unwrap();
+        let addr = listener.local_addr();
+
+        let mut c_stream = ::tokio::net::TcpStream::connect(addr).await.unwrap();
+        let client_addr = c_stream.local_addr().unwrap();
+        let client = crate::tokio_io::client_side(&mut c_stream, &net_key, &ckey, &wrong_pk);
+        let (c_out, s_out) = join(client, listener.accept()).await;
+        assert!(c_out.is_err());
+        match s_out {
+            Err(AcceptError::Handshake {
+                addr,
+                error: HandshakeError::ClientAuthVerifyFailed,
+            }) => assert_eq!(addr, client_addr),
+            _ => panic!(),
+        };
+    }
+
+    #[cfg(feature = "net")]
+    #[::tokio::test]
+    async fn tcp_listener_limits_pending_handshakes() {
+        use crate::net::{connect, AcceptError, ListenerOptions, ShsListener};
+
+        let skey = Keypair::generate();
+        let spk = skey.public;
+        let ckey = Keypair::generate();
+        let net_key = NetworkKey::SSB_MAIN_NET;
+
+        let config = ServerConfig::new(net_key.clone(), skey).unwrap();
+        let options = ListenerOptions::new(config)
+            .max_handshakes(1)
+            .timeouts(Timeouts {
+                handshake: Some(Duration::from_millis(200)),
+                message: None,
+            });
+        let mut listener = ShsListener::bind("127.0.0.1:0", options).await.unwrap();
+        let addr = listener.local_addr();
+
+        // The silent peer holds the only slot until its handshake times out.
+        let silent = ::tokio::net::TcpStream::connect(addr).await.unwrap();
+        let silent_addr = silent.local_addr().unwrap();
+        match listener.accept().await {
+            Err(AcceptError::Handshake {
+                addr,
+                error:
+                    HandshakeError::Timeout {
+                        stage: Stage::ClientHello,
+                    },
+            }) => assert_eq!(addr, silent_addr),
+            _ => panic!(),
+        };
+
+        let (c_out, s_out) = join(connect(addr, &net_key, &ckey, &spk), listener.accept()).await;
+        assert!(c_out.is_ok());
+        assert_eq!(s_out.unwrap().1.keys.peer_key, ckey.public);
+    }
+
+    #[test]
+    fn feed_ids() {
+        let s = "@NNpMLXoHUbSTSMnxlWHM7RqXUHpjfA3EhJ1yVtt1i5U=.ed25519";
+        let id: FeedId = s.parse().unwrap();
+        assert_eq!(id.to_string(), s);
+
+        let key = Keypair::generate();
+        let id = FeedId(key.public);
+        assert_eq!(id.to_string().parse::<FeedId>().unwrap(), id);
+
+        let bad = |s: &str| s.parse::<FeedId>().unwrap_err();
+        assert_eq!(
+            bad("NNpMLXoHUbSTSMnxlWHM7RqXUHpjfA3EhJ1yVtt1i5U=.ed25519"),
+            ParseFeedIdError::MissingSigil
+        );
+        assert_eq!(
+            bad("@NNpMLXoHUbSTSMnxlWHM7RqXUHpjfA3EhJ1yVtt1i5U=.sha256"),
+            ParseFeedIdError::UnsupportedAlgorithm
+        );
+        assert_eq!(bad("@not base64!.ed25519"), ParseFeedIdError::InvalidBase64);
+        assert_eq!(bad("@AAAA.ed25519"), ParseFeedIdError::InvalidKey);
+    }
+
+    #[cfg(feature = "secret-file")]
+    #[test]
+    fn secret_file() {
+        use crate::secret_file::*;
+
+        let dir = std::env::temp_dir().join(format!("ssb-handshake-test-{}", std::process::id()));
+        let path = dir.join("secret");
+        let created = create(&path).unwrap();
+        assert!(create(&path).is_err());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let loaded = load(&path).unwrap();
+        assert_eq!(loaded.public, created.public);
+        assert_eq!(load_or_create(&path).unwrap().public, created.public);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        match parse("# comment\n{ \"curve\": ") {
+            Err(SecretFileError::Json(_)) => {}
+            _ => panic!(),
+        };
+
+        let other = FeedId(Keypair::generate().public).to_string();
+        let mismatched = format(&created).replace(
+            &format!("\"public\": \"{}", &FeedId(created.public).to_string()[1..]),
+            &format!("\"public\": \"{}", &other[1..]),
+        );
+        match parse(&mismatched) {
+            Err(SecretFileError::KeyMismatch) => {}
+            _ => panic!(),
+        };
+    }
+
+    #[cfg(feature = "invite")]
+    #[test]
+    fn invite_codes() {
+        use crate::invite::*;
+
+        let s = "pub.example.com:8008:@NNpMLXoHUbSTSMnxlWHM7RqXUHpjfA3EhJ1yVtt1i5U=.ed25519~\
+                 Cq1Cz5pGOwLE6fvKvJXrqv8vt7/8F2ftB4x2wCYVyNA=";
+        let invite: Invite = s.parse().unwrap();
+        assert_eq!(invite.host, "pub.example.com");
+        assert_eq!(invite.port, 8008);
+        assert_eq!(invite.to_string(), s);
+
+        // Known answer: the keypair is the ed25519 keypair with the invite's seed.
+        assert_eq!(
+            FeedId(invite.keypair().public).to_string(),
+            "@tA2LxCZWZBvttg5mXN6RQ0Fe5KxhoNAbIIRQYY4nEcs=.ed25519"
+        );
+
+        // The seed isn't shown by Debug.
+        let debug = format!("{:?}", invite);
+        assert!(debug.contains("pub.example.com"));
+        assert!(!debug.contains("Cq1Cz5pGOwLE6fvKvJXrqv8vt7"));
+        assert!(!debug.contains(&format!("{:?}", invite.seed())));
+
+        let bad = |s: &str| s.parse::<Invite>().unwrap_err();
+        assert_eq!(bad("pub.example.com:8008"), ParseInviteError::Malformed);
+        assert_eq!(
+            bad("pub.example.com:x:@NNpMLXoHUbSTSMnxlWHM7RqXUHpjfA3EhJ1yVtt1i5U=.ed25519~AAAA"),
+            ParseInviteError::InvalidPort
+        );
+        assert_eq!(
+            bad("pub.example.com:8008:@NNpMLXoHUbSTSMnxlWHM7RqXUHpjfA3EhJ1yVtt1i5U=.ed25519~AAAA"),
+            ParseInviteError::InvalidSeed
+        );
+    }
+
+    #[cfg(all(feature = "invite", feature = "net"))]
+    #[::tokio::test]
+    async fn redeem_invite_with_local_server() {
+        use crate::invite::{redeem, Invite};
+        use crate::net::{ListenerOptions, ShsListener};
+
+        let pub_key = Keypair::generate();
+        let pub_pk = pub_key.public;
+        let net_key = NetworkKey::SSB_MAIN_NET;
+        let config = ServerConfig::new(net_key.clone(), pub_key).unwrap();
+        let mut listener = ShsListener::bind("127.0.0.1:0", ListenerOptions::new(config))
+            .await
+            .unwrap();
+
+        let invite = Invite::new(
+            "127.0.0.1".to_string(),
+            listener.local_addr().port(),
+            pub_pk,
+            [7; 32],
+        );
+        let invite: Invite = invite.to_string().parse().unwrap();
+
+        let (c_out, s_out) = join(redeem(&invite, &net_key), listener.accept()).await;
+        let (_, c_out) = c_out.unwrap();
+        let (_, s_out, _) = s_out.unwrap();
+        assert_eq!(s_out.keys.peer_key, invite.keypair().public);
+        assert_eq!(c_out.keys.peer_key, pub_pk);
+    }
+
+    #[cfg(feature = "proxy")]
+    #[::tokio::test]
+    async fn proxy_forwards_allowed_clients() {
+        use crate::proxy::{ProxyClient, ProxyServer, DEFAULT_TIMEOUTS};
+        use ::tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use ::tokio::net::{TcpListener, TcpStream};
+
+        // Upstream echo service
+        let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream_addr = upstream.local_addr().unwrap();
+        ::tokio::spawn(async move {
+            loop {
+                let (mut s, _) = upstream.accept().await.unwrap();
+                ::tokio::spawn(async move {
+                    let (mut r, mut w) = s.split();
+                    let _ = ::tokio::io::copy(&mut r, &mut w).await;
+                });
+            }
+        });
+
+        let net_key = NetworkKey::SSB_MAIN_NET;
+        let skey = Keypair::generate();
+        let spk = skey.public;
+        let ckey = Keypair::generate();
+        let allowed = vec![ckey.public];
+        let server = ProxyServer::bind(
+            "127.0.0.1:0",
+            upstream_addr,
+            allowed,
+            net_key.clone(),
+            skey,
+            DEFAULT_TIMEOUTS,
+        )
+        .await
+        .unwrap();
+        let server_addr = server.local_addr().unwrap();
+        ::tokio::spawn(server.run());
+
+        let client = ProxyClient::bind(
+            "127.0.0.1:0",
+            server_addr,
+            spk,
+            net_key.clone(),
+            ckey,
+            DEFAULT_TIMEOUTS,
+        )
+        .await
+        .unwrap();
+        let client_addr = client.local_addr().unwrap();
+        ::tokio::spawn(client.run());
+
+        let mut s = TcpStream::connect(client_addr).await.unwrap();
+        s.write_all(b"hello upstream").await.unwrap();
+        let mut buf = [0; 14];
+        s.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello upstream");
+
+        // A client that isn't on the allowlist is disconnected.
+        let other = ProxyClient::bind(
+            "127.0.0.1:0",
+            server_addr,
+            spk,
+            net_key,
+            Keypair::generate(),
+            DEFAULT_TIMEOUTS,
+        )
+        .await
+        .unwrap();
+        let other_addr = other.local_addr().unwrap();
+        ::tokio::spawn(other.run());
+
+        let mut s = TcpStream::connect(other_addr).await.unwrap();
+        let _ = s.write_all(b"hello upstream").await;
+        assert!(!matches!(s.read(&mut buf).await, Ok(n) if n > 0));
+    }
+
+    #[cfg(feature = "proxy")]
+    #[::tokio::test]
+    async fn proxy_rejects_client_without_dialing_upstream() {
+        use crate::proxy::{ProxyServer, DEFAULT_TIMEOUTS};
+        use ::tokio::net::TcpListener;
+        use futures::io::AsyncReadExt;
+        use futures::FutureExt;
+        use tokio_util::compat::TokioAsyncReadCompatExt;
+
+        // Upstream service that should never be connected to.
+        let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream_addr = upstream.local_addr().unwrap();
+
+        let net_key = NetworkKey::SSB_MAIN_NET;
+        let skey = Keypair::generate();
+        let spk = skey.public;
+        let allowed = vec![Keypair::generate().public];
+        let server = ProxyServer::bind(
+            "127.0.0.1:0",
+            upstream_addr,
+            allowed,
+            net_key.clone(),
+            skey,
+            DEFAULT_TIMEOUTS,
+        )
+        .await
+        .unwrap();
+        let server_addr = server.local_addr().unwrap();
+        ::tokio::spawn(server.run());
+
+        let stream = ::tokio::net::TcpStream::connect(server_addr).await.unwrap();
+        let mut stream = stream.compat();
+        let ckey = Keypair::generate();
+        let r = client_side(&mut stream, &net_key, &ckey, &spk).await;
+        assert!(is_eof_err(&r));
+
+        // The server closed the connection without dialing upstream.
+        let mut buf = [0; 1];
+        assert_eq!(stream.read(&mut buf).await.unwrap_or(0), 0);
+        assert!(upstream.accept().now_or_never().is_none());
+    }
+
+    #[cfg(feature = "multiserver")]
+    #[test]
+    fn multiserver_addresses() {
+        use crate::multiserver::*;
+
+        let key = "NNpMLXoHUbSTSMnxlWHM7RqXUHpjfA3EhJ1yVtt1i5U=";
+        let s = format!("net:1.2.3.4:8008~shs:{}", key);
+        let addr: Address = s.parse().unwrap();
+        assert_eq!(addr.transport, Transport::Net);
+        assert_eq!(addr.host, "1.2.3.4");
+        assert_eq!(addr.port, 8008);
+        assert_eq!(addr.to_string(), s);
+
+        let addr: Address = format!("net:fe80::1:8008~shs:{}", key).parse().unwrap();
+        assert_eq!(addr.host, "fe80::1");
+
+        let s = format!("ws://example.com:8989~shs:{}", key);
+        let addr: Address = s.parse().unwrap();
+        assert_eq!(addr.transport, Transport::Ws);
+        assert_eq!(addr.to_string(), s);
+
+        // The short ws form is parsed, but formatted in the canonical form.
+        let addr: Address = format!("ws:example.com:8989~shs:{}", key).parse().unwrap();
+        assert_eq!(addr.to_string(), s);
+
+        let s = format!("onion:abcdefgh.onion:8008~shs:{}", key);
+        let addr: Address = s.parse().unwrap();
+        assert_eq!(addr.transport, Transport::Onion);
+        assert_eq!(addr.to_string(), s);
+
+        // Formatting and parsing again gives back the same address.
+        for s in &[
+            format!("net:1.2.3.4:8008~shs:{}", key),
+            format!("net:fe80::1:8008~shs:{}", key),
+            format!("net:[fe80::1]:8008~shs:{}", key),
+            format!("ws:example.com:8989~shs:{}", key),
+            format!("onion:abcdefgh.onion:8008~shs:{}", key),
+        ] {
+            let addr: Address = s.parse().unwrap();
+            assert_eq!(addr.to_string().parse::<Address>().unwrap(), addr);
+        }
+
+        let bad = |s: &str| s.parse::<Address>().unwrap_err();
+        assert_eq!(bad("net:1.2.3.4:8008"), ParseAddressError::MissingShs);
+        assert_eq!(
+            bad(&format!("dht:1.2.3.4:8008~shs:{}", key)),
+            ParseAddressError::UnknownTransport
+        );
+        assert_eq!(
+            bad(&format!("net:1.2.3.4~shs:{}", key)),
+            ParseAddressError::InvalidPort
+        );
+        assert_eq!(
+            bad("net:1.2.3.4:8008~shs:AAAA"),
+            ParseAddressError::InvalidKey
+        );
+    }
+
+    #[cfg(feature = "transcript")]
+    #[test]
+    fn transcript_replay() {
+        use crate::transcript::*;
+
+        let (mut c_stream, s_stream) = Duplex::pair(1024);
+        let skey = Keypair::generate();
+        let ckey = Keypair::generate();
+        let net_key = NetworkKey::SSB_MAIN_NET;
+
+        let mut recorder = TranscriptRecorder::new(s_stream, Role::Server);
+        let client = client_side(&mut c_stream, &net_key, &ckey, &skey.public);
+        let server = server_side_with(
+            &mut recorder,
+            ServerOptions::new(&net_key, &skey).eph_keypair(server_eph_keypair()),
+        );
+        let (c_out, s_out) = block_on(async { join(client, server).await });
+        c_out.unwrap();
+        let s_out = s_out.unwrap().keys;
+
+        let t = recorder.transcript();
+        assert_eq!(t.messages.len(), 4);
+        let t: Transcript = t.to_string().parse().unwrap();
+
+        let keys = replay_server(&t, &net_key, &skey, server_eph_keypair()).unwrap();
+        assert_eq!(keys.read_key.0, s_out.read_key.0);
+
+        let other = Keypair::generate();
+        let err = replay_server(&t, &net_key, &other, server_eph_keypair()).unwrap_err();
+        assert_eq!(err.stage, Stage::ClientAuth);
+        match err.failure {
+            ReplayFailure::Failed(HandshakeError::ClientAuthVerifyFailed) => {}
+            f => panic!("unexpected failure: {:?}", f),
+        }
+    }
+
+    #[cfg(feature = "boxstream")]
+    #[test]
+    fn boxstream_roundtrip() {
+        use futures::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (c_stream, s_stream) = Duplex::pair(1024);
+        let skey = Keypair::generate();
+        let ckey = Keypair::generate();
+        let net_key = NetworkKey::SSB_MAIN_NET;
+
+        let msg: Vec<u8> = (0..10_000u32).map(|i| i as u8).collect();
+
+        let client = async {
+            let (_r, mut w, peer) = boxstream::client_side(c_stream, &net_key, &ckey, &skey.public)
+                .await
+                .unwrap();
+            assert_eq!(peer, skey.public);
+            w.write_all(&msg).await.unwrap();
+            w.close().await.unwrap();
+        };
+        let server = async {
+            let (mut r, _w, peer) = boxstream::server_side(s_stream, &net_key, &skey)
+                .await
+                .unwrap();
+            assert_eq!(peer, ckey.public);
+            let mut received = Vec::new();
+            r.read_to_end(&mut received).await.unwrap();
+            received
+        };
+
+        let (_, received) = block_on(join(client, server));
+        assert_eq!(received, msg);
+    }
+
+    /// Box-stream encrypts `msg` with a fixed key, including the goodbye header.
+    #[cfg(feature = "boxstream")]
+    fn boxed(msg: &[u8]) -> Vec<u8> {
+        use futures::io::AsyncWriteExt;
+        use ssb_crypto::secretbox::{Key, Nonce};
+
+        let mut w = boxstream::BoxWriter::new(Vec::new(), Key([1; 32]), Nonce([2; 24]));
+        block_on(async {
+            w.write_all(msg).await.unwrap();
+            w.close().await.unwrap();
+        });
+        w.into_inner()
+    }
+
+    #[cfg(feature = "boxstream")]
+    fn unboxed(bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+        use futures::io::{AsyncReadExt, Cursor};
+        use ssb_crypto::secretbox::{Key, Nonce};
+
+        let mut r = boxstream::BoxReader::new(Cursor::new(bytes), Key([1; 32]), Nonce([2; 24]));
+        let mut out = Vec::new();
+        block_on(r.read_to_end(&mut out))?;
+        Ok(out)
+    }
+
+    #[cfg(feature = "boxstream")]
+    #[test]
+    fn boxstream_splits_long_writes() {
+        use boxstream::MAX_BOX_BODY_SIZE;
+        use futures::io::AsyncWriteExt;
+        use ssb_crypto::secretbox::{Key, Nonce};
+
+        let msg: Vec<u8> = (0..2 * MAX_BOX_BODY_SIZE + 1).map(|i| i as u8).collect();
+
+        let mut w = boxstream::BoxWriter::new(Vec::new(), Key([1; 32]), Nonce([2; 24]));
+        assert_eq!(block_on(w.write(&msg)).unwrap(), MAX_BOX_BODY_SIZE);
+
+        // Three boxes and a goodbye, each with a 34-byte header.
+        let bytes = boxed(&msg);
+        assert_eq!(bytes.len(), msg.len() + 4 * 34);
+        assert_eq!(unboxed(&bytes).unwrap(), msg);
+    }
+
+    #[cfg(feature = "boxstream")]
+    #[test]
+    fn boxstream_rejects_flipped_bytes() {
+        let bytes = boxed(b"hello box stream");
+        assert_eq!(unboxed(&bytes).unwrap(), b"hello box stream");
+
+        // Every byte of the first header, body, and the goodbye header is authenticated.
+        for i in 0..bytes.len() {
+            let mut tampered = bytes.clone();
+            tampered[i] ^= 0x01;
+            let e = unboxed(&tampered).unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::InvalidData, "byte {}", i);
+        }
+    }
+
+    #[cfg(feature = "boxstream")]
+    #[test]
+    fn boxstream_rejects_truncated_stream() {
+        let bytes = boxed(b"hello box stream");
+
+        // Ending anywhere before the goodbye header is an error, not a clean EOF.
+        for len in 0..bytes.len() {
+            let e = unboxed(&bytes[..len]).unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::UnexpectedEof, "length {}", len);
+        }
+    }
+
+    #[test]
+    fn wrong_size_messages_fail_to_deserialize() {
+        let skey = Keypair::generate();
+        let net_key = NetworkKey::SSB_MAIN_NET;
+
+        let mut server = ServerHandshake::new(&net_key, &skey, generate_ephemeral_keypair());
+        match server.read_message(&[0; 10]) {
+            Err(HandshakeError::ClientHelloDeserializeFailed) => {}
+            _ => panic!(),
+        };
+
+        assert!(ServerHello::from_bytes(&[0; 65]).is_err());
+        assert!(ClientAuth::from_bytes(&mut [0; 111]).is_err());
+        assert!(ServerAccept::from_bytes(&[]).is_err());
+    }
+
     fn is_eof_err<T>(r: &Result<T, HandshakeError<std::io::Error>>) -> bool {
         match r {
             Err(HandshakeError::Io(e)) => e.kind() == ErrorKind::UnexpectedEof,
@@ -102,7 +1036,7 @@ mod tests {
 
         let net_key = NetworkKey::SSB_MAIN_NET;
 
-        let client_side = client_side(&mut c_stream, &net_key, &ckey, &bad_pk);
+        let client_side = client_side(&mut c_stream, &net_key, &ckey, bad_pk);
         let server_side = server_side(&mut s_stream, &net_key, &skey);
 
         let (c_out, s_out) = block_on(async { join(client_side, server_side).await });
@@ -110,4 +1044,116 @@ mod tests {
         assert!(c_out.is_err());
         assert!(s_out.is_err());
     }
+
+    proptest! {
+        #[test]
+        fn any_bit_flip_fails_at_its_stage(
+            stage in 0..Stage::ALL.len(),
+            bit in any::<prop::sample::Index>(),
+        ) {
+            let tampered = Stage::ALL[stage];
+            let bit = bit.index(tampered.message_size() * 8);
+
+            let keys = Keys::new();
+            let mut client = keys.client(client_eph_keypair());
+            let mut server = keys.server(server_eph_keypair());
+
+            let mut buf = [0u8; MAX_MESSAGE_SIZE];
+            for &stage in Stage::ALL.iter() {
+                let msg = &mut buf[..stage.message_size()];
+                match stage.sender() {
+                    Role::Client => client.write_message(msg),
+                    Role::Server => server.write_message(msg),
+                };
+                if stage == tampered {
+                    msg[bit / 8] ^= 1 << (bit % 8);
+                }
+                let r = match stage.sender() {
+                    Role::Client => server.read_message(msg),
+                    Role::Server => client.read_message(msg),
+                };
+                if stage != tampered {
+                    prop_assert!(r.is_ok());
+                    continue;
+                }
+
+                use HandshakeError::*;
+                let failed_as_expected = matches!(
+                    (stage, r),
+                    (Stage::ClientHello, Err(ClientHelloVerifyFailed))
+                        | (Stage::ServerHello, Err(ServerHelloVerifyFailed))
+                        | (Stage::ClientAuth, Err(ClientAuthVerifyFailed))
+                        | (Stage::ServerAccept, Err(ServerAcceptVerifyFailed))
+                );
+                prop_assert!(failed_as_expected);
+                break;
+            }
+        }
+
+        #[test]
+        fn keys_are_mirrored(
+            client_seed in any::<[u8; 32]>(),
+            server_seed in any::<[u8; 32]>(),
+            client_eph in any::<u64>(),
+            server_eph in any::<u64>(),
+        ) {
+            let skey = Keypair::from_seed(&server_seed).unwrap();
+            let ckey = Keypair::from_seed(&client_seed).unwrap();
+            let net_key = NetworkKey::SSB_MAIN_NET;
+
+            let ex = testing::exchange(
+                ClientHandshake::new(&net_key, &ckey, &skey.public, eph_keypair(client_eph)),
+                ServerHandshake::new(&net_key, &skey, eph_keypair(server_eph)),
+            );
+            let (c_out, s_out) = (ex.client.keys, ex.server.keys);
+            prop_assert_eq!(c_out.write_key.0, s_out.read_key.0);
+            prop_assert_eq!(c_out.read_key.0, s_out.write_key.0);
+            prop_assert_eq!(c_out.write_starting_nonce.0, s_out.read_starting_nonce.0);
+            prop_assert_eq!(c_out.read_starting_nonce.0, s_out.write_starting_nonce.0);
+            prop_assert_eq!(c_out.peer_key, skey.public);
+            prop_assert_eq!(s_out.peer_key, ckey.public);
+        }
+
+        #[test]
+        fn sync_and_async_keys_match(client_eph in any::<u64>(), server_eph in any::<u64>()) {
+            let keys = Keys::new();
+            let (net_key, ckey, skey) = (&keys.net_key, &keys.client, &keys.server);
+
+            let (mut c_stream, mut s_stream) = Duplex::pair(1024);
+            let c_options = ClientOptions::new(net_key, ckey, &skey.public);
+            let s_options = ServerOptions::new(net_key, skey);
+            let client =
+                client_side_with(&mut c_stream, c_options.eph_keypair(eph_keypair(client_eph)));
+            let server =
+                server_side_with(&mut s_stream, s_options.eph_keypair(eph_keypair(server_eph)));
+            let (c_async, s_async) = block_on(async { join(client, server).await });
+            let (c_async, s_async) = (c_async.unwrap().keys, s_async.unwrap().keys);
+
+            // Each sync side replays the messages the other side sends.
+            let ex = testing::exchange(
+                keys.client(eph_keypair(client_eph)),
+                keys.server(eph_keypair(server_eph)),
+            );
+            let c_sync = sync::client_side(
+                ReplayStream::new(&ex.s2c),
+                net_key,
+                ckey,
+                &skey.public,
+                eph_keypair(client_eph),
+            )
+            .unwrap()
+            .keys;
+            let s_stream = ReplayStream::new(&ex.c2s);
+            let s_sync = sync::server_side(s_stream, net_key, skey, eph_keypair(server_eph))
+                .unwrap()
+                .keys;
+
+            for (a, s) in [(&c_async, &c_sync), (&s_async, &s_sync)].iter() {
+                prop_assert_eq!(a.write_key.0, s.write_key.0);
+                prop_assert_eq!(a.read_key.0, s.read_key.0);
+                prop_assert_eq!(a.write_starting_nonce.0, s.write_starting_nonce.0);
+                prop_assert_eq!(a.read_starting_nonce.0, s.read_starting_nonce.0);
+            }
+        }
+    }
 }
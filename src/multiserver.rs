@@ -0,0 +1,156 @@
+//! Multiserver addresses, as used to address SSB peers, eg.
+//! `net:1.2.3.4:8008~shs:<base64 public key>`.
+//!
+//! Addresses with the `net` (plain TCP), `ws` (websockets) and `onion` (tor)
+//! transports, each combined with the `shs` (secret handshake) transform, can be
+//! parsed and formatted. Only `net` addresses can be dialed with [`connect`];
+//! `ws` and `onion` addresses are parsed so they can be stored and passed on.
+//! See the [multiserver-address](https://github.com/ssbc/multiserver-address) spec.
+//!
+//! Formatting a parsed address gives back the same string, except that `ws:host:port`
+//! is written in its canonical form, `ws://host:port`.
+
+use core::fmt;
+use core::str::FromStr;
+use ssb_crypto::PublicKey;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Transport {
+    Net,
+    /// Parsed and formatted only; can't be dialed by this crate.
+    Ws,
+    /// Parsed and formatted only; can't be dialed by this crate.
+    Onion,
+}
+
+/// A peer address: a transport, host and port, and the peer's public key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Address {
+    pub transport: Transport,
+    pub host: String,
+    pub port: u16,
+    pub key: PublicKey,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParseAddressError {
+    /// The address doesn't have the form `<transport>~shs:<key>`.
+    MissingShs,
+    UnknownTransport,
+    InvalidHost,
+    InvalidPort,
+    /// The key isn't a base64 encoded ed25519 public key.
+    InvalidKey,
+}
+
+impl fmt::Display for ParseAddressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ParseAddressError::*;
+        match self {
+            MissingShs => write!(f, "Address is missing the shs transform"),
+            UnknownTransport => write!(f, "Unknown address transport"),
+            InvalidHost => write!(f, "Invalid host in address"),
+            InvalidPort => write!(f, "Invalid port in address"),
+            InvalidKey => write!(f, "Invalid public key in address"),
+        }
+    }
+}
+
+impl std::error::Error for ParseAddressError {}
+
+impl FromStr for Address {
+    type Err = ParseAddressError;
+
+    fn from_str(s: &str) -> Result<Address, ParseAddressError> {
+        use ParseAddressError::*;
+
+        let mut parts = s.splitn(2, '~');
+        let transport = parts.next().unwrap_or("");
+        let key = parts
+            .next()
+            .and_then(|t| t.strip_prefix("shs:"))
+            .ok_or(MissingShs)?;
+
+        let (transport, host_port) = if let Some(hp) = transport.strip_prefix("net:") {
+            (Transport::Net, hp)
+        } else if let Some(hp) = transport
+            .strip_prefix("ws://")
+            .or_else(|| transport.strip_prefix("ws:"))
+        {
+            (Transport::Ws, hp)
+        } else if let Some(hp) = transport.strip_prefix("onion:") {
+            (Transport::Onion, hp)
+        } else {
+            return Err(UnknownTransport);
+        };
+
+        // The host may be an ipv6 address, so split on the last colon.
+        let colon = host_port.rfind(':').ok_or(InvalidPort)?;
+        let host = &host_port[..colon];
+        let port = host_port[colon + 1..].parse().map_err(|_| InvalidPort)?;
+        if host.is_empty() {
+            return Err(InvalidHost);
+        }
+
+        let key = base64::decode(key)
+            .ok()
+            .and_then(|k| PublicKey::from_slice(&k))
+            .ok_or(InvalidKey)?;
+
+        Ok(Address {
+            transport,
+            host: host.to_string(),
+            port,
+            key,
+        })
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let transport = match self.transport {
+            Transport::Net => "net:",
+            Transport::Ws => "ws://",
+            Transport::Onion => "onion:",
+        };
+        write!(
+            f,
+            "{}{}:{}~shs:{}",
+            transport,
+            self.host,
+            self.port,
+            base64::encode(self.key.0)
+        )
+    }
+}
+
+/// Look up the socket addresses of `host` and `port`.
+/// Ipv6 hosts may be written with or without brackets.
+#[cfg(feature = "net")]
+pub async fn resolve(host: &str, port: u16) -> std::io::Result<Vec<std::net::SocketAddr>> {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    Ok(::tokio::net::lookup_host((host, port)).await?.collect())
+}
+
+/// Connect to the peer at the given `net` address, and perform the client side
+/// of the handshake using the address's public key.
+/// Fails with an IO error of kind `InvalidInput` for `ws` and `onion` addresses.
+#[cfg(all(feature = "net", feature = "getrandom"))]
+pub async fn connect(
+    addr: &Address,
+    net_key: &ssb_crypto::NetworkKey,
+    keypair: &ssb_crypto::Keypair,
+) -> Result<(::tokio::net::TcpStream, crate::HandshakeOutcome), crate::HandshakeError<std::io::Error>>
+{
+    use std::io;
+
+    if addr.transport != Transport::Net {
+        let e = io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "only net addresses can be dialed",
+        );
+        return Err(e.into());
+    }
+    let addrs = resolve(&addr.host, addr.port).await?;
+    crate::net::connect(&addrs[..], net_key, keypair, &addr.key).await
+}
@@ -0,0 +1,237 @@
+//! TCP helpers, built on tokio, that hand back connections which have already
+//! completed the handshake.
+
+use crate::config::ServerConfig;
+use crate::crypto::outcome::HandshakeOutcome;
+use crate::error::HandshakeError;
+use crate::options::{AllowAll, AuthorizeAsync, ServerOptions};
+use crate::util::Timeouts;
+
+use ::tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use ::tokio::sync::{mpsc, Semaphore};
+use ::tokio::task::JoinHandle;
+use futures_timer::Delay;
+use ssb_crypto::{Keypair, NetworkKey, PublicKey};
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Number of results that may wait in the queue for `ShsListener::accept`.
+const ACCEPT_QUEUE_SIZE: usize = 32;
+
+/// Handshake time limits that suit most deployments: a peer gets 10 seconds to
+/// complete the handshake.
+pub const DEFAULT_TIMEOUTS: Timeouts = Timeouts {
+    handshake: Some(Duration::from_secs(10)),
+    message: None,
+};
+
+/// The default for `ListenerOptions::max_handshakes`.
+pub const DEFAULT_MAX_HANDSHAKES: usize = 64;
+
+/// Pause after a failed TCP accept (eg. when out of file descriptors),
+/// so the accept loop doesn't spin.
+pub(crate) const ACCEPT_ERROR_DELAY: Duration = Duration::from_millis(100);
+
+/// Connect to the given address, and perform the client side of the handshake.
+/// Returns the stream, ready for box-stream encryption, and the handshake outcome.
+pub async fn connect<A: ToSocketAddrs>(
+    addr: A,
+    net_key: &NetworkKey,
+    keypair: &Keypair,
+    server_pk: &PublicKey,
+) -> Result<(TcpStream, HandshakeOutcome), HandshakeError<io::Error>> {
+    let mut stream = TcpStream::connect(addr).await?;
+    let outcome = crate::tokio_io::client_side(&mut stream, net_key, keypair, server_pk).await?;
+    Ok((stream, outcome))
+}
+
+/// Error returned by `ShsListener::accept`.
+/// The listener keeps running after either kind of error.
+#[derive(Debug)]
+pub enum AcceptError {
+    /// Accepting a TCP connection failed, or the listener has stopped.
+    Io(io::Error),
+    /// The handshake with the peer at `addr` failed, and the connection was closed.
+    Handshake {
+        addr: SocketAddr,
+        error: HandshakeError<io::Error>,
+    },
+}
+
+impl fmt::Display for AcceptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AcceptError::Io(e) => write!(f, "accept failed: {}", e),
+            AcceptError::Handshake { addr, error } => {
+                write!(f, "{}: handshake failed: {}", addr, error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AcceptError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AcceptError::Io(e) => Some(e),
+            AcceptError::Handshake { error, .. } => Some(error),
+        }
+    }
+}
+
+type Accepted = Result<(TcpStream, HandshakeOutcome, SocketAddr), AcceptError>;
+
+/// Options for `ShsListener::bind`.
+pub struct ListenerOptions<A = AllowAll> {
+    config: ServerConfig,
+    timeouts: Timeouts,
+    max_handshakes: usize,
+    authorize: A,
+}
+
+impl ListenerOptions {
+    /// Lets every client in, with `DEFAULT_TIMEOUTS` and `DEFAULT_MAX_HANDSHAKES`.
+    pub fn new(config: ServerConfig) -> ListenerOptions {
+        ListenerOptions {
+            config,
+            timeouts: DEFAULT_TIMEOUTS,
+            max_handshakes: DEFAULT_MAX_HANDSHAKES,
+            authorize: AllowAll,
+        }
+    }
+}
+
+impl<A> ListenerOptions<A> {
+    /// Fail handshakes with `HandshakeError::Timeout` if the client is too slow.
+    /// A listener always has a handshake deadline: if `timeouts.handshake` is `None`,
+    /// the one from `DEFAULT_TIMEOUTS` is used.
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = Timeouts {
+            handshake: timeouts.handshake.or(DEFAULT_TIMEOUTS.handshake),
+            ..timeouts
+        };
+        self
+    }
+
+    /// The number of handshakes that may be in progress at once.
+    /// When it's reached, no more connections are accepted until one finishes.
+    ///
+    /// # Panics
+    /// If `max` is 0.
+    pub fn max_handshakes(mut self, max: usize) -> Self {
+        assert!(max > 0, "max_handshakes must be at least 1");
+        self.max_handshakes = max;
+        self
+    }
+
+    /// Ask `authorize` whether each client should be let in; see
+    /// `ServerOptions::authorize`. It's cloned for each connection.
+    pub fn authorize<B: AuthorizeAsync + Clone>(self, authorize: B) -> ListenerOptions<B> {
+        ListenerOptions {
+            config: self.config,
+            timeouts: self.timeouts,
+            max_handshakes: self.max_handshakes,
+            authorize,
+        }
+    }
+}
+
+/// A TCP listener that performs the server side of the handshake on each
+/// incoming connection.
+///
+/// Handshakes run concurrently in their own tasks, so a slow peer doesn't hold up
+/// the others. Each handshake has a deadline, and at most `max_handshakes` run at
+/// once, so slow peers can't tie up resources forever.
+/// Connections that fail the handshake are closed, and the failure is returned by
+/// `accept`.
+/// Must be created within a tokio runtime.
+pub struct ShsListener {
+    local_addr: SocketAddr,
+    incoming: mpsc::Receiver<Accepted>,
+    task: JoinHandle<()>,
+}
+
+impl ShsListener {
+    pub async fn bind<T, A>(addr: T, options: ListenerOptions<A>) -> io::Result<ShsListener>
+    where
+        T: ToSocketAddrs,
+        A: AuthorizeAsync + Clone + Send + Sync + 'static,
+        A::Future: Send,
+    {
+        let listener = TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+        let (tx, incoming) = mpsc::channel(ACCEPT_QUEUE_SIZE);
+        let task = ::tokio::spawn(accept_loop(listener, Arc::new(options), tx));
+
+        Ok(ShsListener {
+            local_addr,
+            incoming,
+            task,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Wait for the next connection to complete (or fail) the handshake.
+    /// Returns the stream, the handshake outcome, and the peer's address.
+    /// Errors from accepting TCP connections and failed handshakes are passed
+    /// along here, in the order they happen.
+    pub async fn accept(
+        &mut self,
+    ) -> Result<(TcpStream, HandshakeOutcome, SocketAddr), AcceptError> {
+        match self.incoming.recv().await {
+            Some(r) => r,
+            None => Err(AcceptError::Io(io::Error::other("listener closed"))),
+        }
+    }
+}
+
+impl Drop for ShsListener {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn accept_loop<A>(
+    listener: TcpListener,
+    options: Arc<ListenerOptions<A>>,
+    tx: mpsc::Sender<Accepted>,
+) where
+    A: AuthorizeAsync + Clone + Send + Sync + 'static,
+    A::Future: Send,
+{
+    let slots = Arc::new(Semaphore::new(options.max_handshakes));
+    loop {
+        // The semaphore is never closed.
+        let permit = slots.clone().acquire_owned().await.unwrap();
+        let (mut stream, addr) = match listener.accept().await {
+            Ok(s) => s,
+            Err(e) => {
+                if tx.send(Err(AcceptError::Io(e))).await.is_err() {
+                    return;
+                }
+                Delay::new(ACCEPT_ERROR_DELAY).await;
+                continue;
+            }
+        };
+
+        let options = options.clone();
+        let tx = tx.clone();
+        ::tokio::spawn(async move {
+            let server_options = ServerOptions::with_config(&options.config)
+                .timeouts(options.timeouts)
+                .authorize(options.authorize.clone());
+            let r = match crate::tokio_io::server_side_with(&mut stream, server_options).await {
+                Ok(outcome) => Ok((stream, outcome, addr)),
+                Err(error) => Err(AcceptError::Handshake { addr, error }),
+            };
+            drop(permit);
+            // If the listener has been dropped, so is the connection.
+            let _ = tx.send(r).await;
+        });
+    }
+}
@@ -0,0 +1,298 @@
+//! Options for the `server_side_with` and `client_side_with` handshake functions.
+//!
+//! The required keys are given to the constructor; everything else is optional
+//! and set with builder methods, eg.
+//! `ServerOptions::new(&net_key, &keypair).timeouts(timeouts).authorize(f)`.
+
+use crate::config::{ClientConfig, ServerConfig};
+use crate::handshake::{ClientHandshake, ServerHandshake};
+#[cfg(feature = "std")]
+use crate::util::Timeouts;
+
+use core::future::{ready, Future, Ready};
+use core::slice;
+use rand_core::{CryptoRng, RngCore};
+#[cfg(feature = "getrandom")]
+use ssb_crypto::ephemeral::generate_ephemeral_keypair;
+use ssb_crypto::ephemeral::{generate_ephemeral_keypair_with_rng, EphPublicKey, EphSecretKey};
+use ssb_crypto::{Keypair, NetworkKey, PublicKey};
+
+/// Decides whether a client that has proven its identity is let in,
+/// in the blocking `sync::server_side_with`.
+///
+/// Implemented for `FnOnce(&PublicKey) -> bool` closures, and can be implemented
+/// for other types, eg. an allowlist.
+pub trait Authorize {
+    fn authorize(self, client_pk: &PublicKey) -> bool;
+}
+
+impl<F> Authorize for F
+where
+    F: FnOnce(&PublicKey) -> bool,
+{
+    fn authorize(self, client_pk: &PublicKey) -> bool {
+        self(client_pk)
+    }
+}
+
+/// Decides whether a client that has proven its identity is let in,
+/// in the async `server_side_with`.
+///
+/// Implemented for `FnOnce(&PublicKey) -> impl Future<Output = bool>` closures,
+/// and can be implemented for other types, eg. an allowlist.
+pub trait AuthorizeAsync {
+    type Future: Future<Output = bool>;
+    fn authorize(self, client_pk: &PublicKey) -> Self::Future;
+}
+
+impl<F, Fut> AuthorizeAsync for F
+where
+    F: FnOnce(&PublicKey) -> Fut,
+    Fut: Future<Output = bool>,
+{
+    type Future = Fut;
+    fn authorize(self, client_pk: &PublicKey) -> Fut {
+        self(client_pk)
+    }
+}
+
+/// Lets every client in. The default for `ServerOptions`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct AllowAll;
+
+impl Authorize for AllowAll {
+    fn authorize(self, _: &PublicKey) -> bool {
+        true
+    }
+}
+
+impl AuthorizeAsync for AllowAll {
+    type Future = Ready<bool>;
+    fn authorize(self, _: &PublicKey) -> Ready<bool> {
+        ready(true)
+    }
+}
+
+enum ServerKeys<'a> {
+    Keys {
+        net_keys: &'a [NetworkKey],
+        keypairs: &'a [Keypair],
+    },
+    Config(&'a ServerConfig),
+}
+
+/// Options for the server side of the handshake.
+pub struct ServerOptions<'a, A = AllowAll> {
+    keys: ServerKeys<'a>,
+    eph_kp: Option<(EphPublicKey, EphSecretKey)>,
+    #[cfg(feature = "std")]
+    pub(crate) timeouts: Timeouts,
+    pub(crate) authorize: A,
+}
+
+impl<'a> ServerOptions<'a> {
+    pub fn new(net_key: &'a NetworkKey, keypair: &'a Keypair) -> ServerOptions<'a> {
+        ServerOptions::with_keypairs(slice::from_ref(net_key), slice::from_ref(keypair))
+    }
+
+    /// Accept clients on any of the given networks (eg. the main net and a test net
+    /// on the same port). The network the client joined is given by the outcome's
+    /// `net_key` and `net_key_index`.
+    /// The handshake fails with `HandshakeError::ClientHelloVerifyFailed` if the
+    /// client's hello doesn't match any of the network keys.
+    pub fn with_network_keys(
+        net_keys: &'a [NetworkKey],
+        keypair: &'a Keypair,
+    ) -> ServerOptions<'a> {
+        ServerOptions::with_keypairs(net_keys, slice::from_ref(keypair))
+    }
+
+    /// Accept clients on any of the given networks, answering as whichever of the
+    /// given long-term identities the client is connecting to (eg. an old and a
+    /// rotated identity). The identity that was used is given by the outcome's
+    /// `keypair_index`.
+    /// The handshake fails with `HandshakeError::ClientAuthVerifyFailed` if the
+    /// client is trying to connect to some other identity.
+    pub fn with_keypairs(net_keys: &'a [NetworkKey], keypairs: &'a [Keypair]) -> ServerOptions<'a> {
+        ServerOptions {
+            keys: ServerKeys::Keys { net_keys, keypairs },
+            eph_kp: None,
+            #[cfg(feature = "std")]
+            timeouts: Timeouts::NONE,
+            authorize: AllowAll,
+        }
+    }
+
+    /// Use the key prepared in advance by a `ServerConfig`.
+    /// Useful for servers that accept many connections.
+    pub fn with_config(config: &'a ServerConfig) -> ServerOptions<'a> {
+        ServerOptions {
+            keys: ServerKeys::Config(config),
+            ..ServerOptions::new(&config.net_key, &config.keypair)
+        }
+    }
+}
+
+impl<'a, A> ServerOptions<'a, A> {
+    /// Fail with `HandshakeError::Timeout` if the client is too slow to respond.
+    /// The authorization check isn't subject to the timeouts.
+    ///
+    /// Timeouts are only enforced by the async handshake functions.
+    #[cfg(feature = "std")]
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Use the given ephemeral keypair instead of generating one.
+    /// The ephemeral keypair must never be reused for another handshake.
+    pub fn eph_keypair(mut self, eph_kp: (EphPublicKey, EphSecretKey)) -> Self {
+        self.eph_kp = Some(eph_kp);
+        self
+    }
+
+    /// Generate the ephemeral keypair with the given rng.
+    pub fn rng<R>(self, rng: &mut R) -> Self
+    where
+        R: CryptoRng + RngCore,
+    {
+        self.eph_keypair(generate_ephemeral_keypair_with_rng(rng))
+    }
+
+    /// Ask `authorize` whether the client should be let in once it has proven its
+    /// identity, but before the final `ServerAccept` message is sent.
+    /// If the client is rejected, the handshake fails with `HandshakeError::PeerRejected`.
+    ///
+    /// For the async handshake functions. Closures need their argument type spelled
+    /// out, eg. `.authorize(|pk: &PublicKey| ready(allowed.contains(pk)))`.
+    pub fn authorize<B: AuthorizeAsync>(self, authorize: B) -> ServerOptions<'a, B> {
+        self.with_authorize(authorize)
+    }
+
+    /// As `authorize`, for `sync::server_side_with`, eg.
+    /// `.authorize_sync(|pk: &PublicKey| allowed.contains(pk))`.
+    pub fn authorize_sync<B: Authorize>(self, authorize: B) -> ServerOptions<'a, B> {
+        self.with_authorize(authorize)
+    }
+
+    fn with_authorize<B>(self, authorize: B) -> ServerOptions<'a, B> {
+        ServerOptions {
+            keys: self.keys,
+            eph_kp: self.eph_kp,
+            #[cfg(feature = "std")]
+            timeouts: self.timeouts,
+            authorize,
+        }
+    }
+
+    /// The handshake state machine, consuming the ephemeral keypair.
+    pub(crate) fn handshake(&mut self) -> ServerHandshake<'a> {
+        let eph_kp = take_eph_keypair(&mut self.eph_kp);
+        match self.keys {
+            ServerKeys::Keys { net_keys, keypairs } => {
+                ServerHandshake::with_keypairs(net_keys, keypairs, eph_kp)
+            }
+            ServerKeys::Config(config) => ServerHandshake::with_config(config, eph_kp),
+        }
+    }
+}
+
+enum ClientKeys<'a> {
+    Keys {
+        net_key: &'a NetworkKey,
+        keypair: &'a Keypair,
+        server_pk: &'a PublicKey,
+    },
+    Config(&'a ClientConfig),
+}
+
+/// Options for the client side of the handshake.
+pub struct ClientOptions<'a> {
+    keys: ClientKeys<'a>,
+    eph_kp: Option<(EphPublicKey, EphSecretKey)>,
+    #[cfg(feature = "std")]
+    pub(crate) timeouts: Timeouts,
+}
+
+impl<'a> ClientOptions<'a> {
+    pub fn new(
+        net_key: &'a NetworkKey,
+        keypair: &'a Keypair,
+        server_pk: &'a PublicKey,
+    ) -> ClientOptions<'a> {
+        ClientOptions {
+            keys: ClientKeys::Keys {
+                net_key,
+                keypair,
+                server_pk,
+            },
+            eph_kp: None,
+            #[cfg(feature = "std")]
+            timeouts: Timeouts::NONE,
+        }
+    }
+
+    /// Use the keys prepared in advance by a `ClientConfig`.
+    /// Useful when connecting to the same server many times.
+    pub fn with_config(config: &'a ClientConfig) -> ClientOptions<'a> {
+        ClientOptions {
+            keys: ClientKeys::Config(config),
+            ..ClientOptions::new(&config.net_key, &config.keypair, &config.server_pk)
+        }
+    }
+
+    /// Fail with `HandshakeError::Timeout` if the server is too slow to respond.
+    ///
+    /// Timeouts are only enforced by the async handshake functions.
+    #[cfg(feature = "std")]
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Use the given ephemeral keypair instead of generating one.
+    /// The ephemeral keypair must never be reused for another handshake.
+    pub fn eph_keypair(mut self, eph_kp: (EphPublicKey, EphSecretKey)) -> Self {
+        self.eph_kp = Some(eph_kp);
+        self
+    }
+
+    /// Generate the ephemeral keypair with the given rng.
+    pub fn rng<R>(self, rng: &mut R) -> Self
+    where
+        R: CryptoRng + RngCore,
+    {
+        self.eph_keypair(generate_ephemeral_keypair_with_rng(rng))
+    }
+
+    /// The handshake state machine, consuming the ephemeral keypair.
+    pub(crate) fn handshake(&mut self) -> ClientHandshake<'a> {
+        let eph_kp = take_eph_keypair(&mut self.eph_kp);
+        match self.keys {
+            ClientKeys::Keys {
+                net_key,
+                keypair,
+                server_pk,
+            } => ClientHandshake::new(net_key, keypair, server_pk, eph_kp),
+            ClientKeys::Config(config) => ClientHandshake::with_config(config, eph_kp),
+        }
+    }
+}
+
+/// The ephemeral keypair that was set, or a freshly generated one.
+///
+/// Without the `getrandom` feature there is no default source of randomness,
+/// so one of `eph_keypair` or `rng` has to be used.
+fn take_eph_keypair(
+    eph_kp: &mut Option<(EphPublicKey, EphSecretKey)>,
+) -> (EphPublicKey, EphSecretKey) {
+    match eph_kp.take() {
+        Some(kp) => kp,
+        #[cfg(feature = "getrandom")]
+        None => generate_ephemeral_keypair(),
+        #[cfg(not(feature = "getrandom"))]
+        None => panic!(
+            "no ephemeral keypair: use `eph_keypair` or `rng`, or enable the `getrandom` feature"
+        ),
+    }
+}
@@ -0,0 +1,221 @@
+//! An encrypting TCP proxy (like stunnel), for putting plaintext services
+//! behind SSB identities.
+//!
+//! ```text
+//! plaintext client -> ProxyClient ==(handshake + box-stream)==> ProxyServer -> upstream service
+//! ```
+//!
+//! The [`ProxyClient`] accepts plaintext connections, and for each one does the
+//! client side of the handshake with the remote [`ProxyServer`].
+//! The server only lets in clients whose public keys are on its allowlist,
+//! and forwards their traffic to the upstream service.
+
+use crate::boxstream::{box_stream, MAX_BOX_BODY_SIZE};
+use crate::config::{ClientConfig, ServerConfig};
+use crate::crypto::outcome::HandshakeKeys;
+use crate::net::ACCEPT_ERROR_DELAY;
+use crate::options::{ClientOptions, ServerOptions};
+use crate::util::Timeouts;
+
+use ::tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use futures_io::{AsyncRead, AsyncWrite};
+use futures_timer::Delay;
+use futures_util::future::{ready, try_join};
+use futures_util::io::{AsyncReadExt, AsyncWriteExt};
+use ssb_crypto::{Keypair, NetworkKey, PublicKey};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio_util::compat::{Compat, TokioAsyncReadCompatExt};
+
+pub use crate::net::DEFAULT_TIMEOUTS;
+
+/// Accepts plaintext connections, and forwards them over an encrypted
+/// connection to a `ProxyServer`.
+pub struct ProxyClient {
+    listener: TcpListener,
+    settings: Arc<ClientSettings>,
+}
+
+struct ClientSettings {
+    remote: SocketAddr,
+    config: ClientConfig,
+    timeouts: Timeouts,
+}
+
+impl ProxyClient {
+    /// Fails with an IO error of kind `InvalidInput` if the keypair or `remote_pk`
+    /// can't be converted to curve25519.
+    pub async fn bind<A: ToSocketAddrs>(
+        addr: A,
+        remote: SocketAddr,
+        remote_pk: PublicKey,
+        net_key: NetworkKey,
+        keypair: Keypair,
+        timeouts: Timeouts,
+    ) -> io::Result<ProxyClient> {
+        let config = ClientConfig::new(net_key, keypair, remote_pk).ok_or_else(invalid_key)?;
+        Ok(ProxyClient {
+            listener: TcpListener::bind(addr).await?,
+            settings: Arc::new(ClientSettings {
+                remote,
+                config,
+                timeouts,
+            }),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accept and forward connections, each in its own task. Never returns.
+    /// If accepting a connection fails (eg. when out of file descriptors),
+    /// tries again after a short delay.
+    pub async fn run(self) {
+        loop {
+            let (local, _) = match self.listener.accept().await {
+                Ok(s) => s,
+                Err(_) => {
+                    Delay::new(ACCEPT_ERROR_DELAY).await;
+                    continue;
+                }
+            };
+            let settings = self.settings.clone();
+            ::tokio::spawn(async move {
+                // There's no one to report the error to; the connection is just closed.
+                let _ = forward_to_server(local, &settings).await;
+            });
+        }
+    }
+}
+
+async fn forward_to_server(local: TcpStream, settings: &ClientSettings) -> io::Result<()> {
+    let mut remote = TcpStream::connect(settings.remote).await?.compat();
+    let options = ClientOptions::with_config(&settings.config).timeouts(settings.timeouts);
+    let outcome = crate::client_side_with(&mut remote, options)
+        .await
+        .map_err(handshake_error)?;
+    pipe(local.compat(), remote, outcome.keys).await
+}
+
+/// Accepts encrypted connections from `ProxyClient`s on its allowlist,
+/// and forwards them as plaintext to the upstream service.
+/// The upstream service is only dialed once a client has been let in.
+pub struct ProxyServer {
+    listener: TcpListener,
+    settings: Arc<ServerSettings>,
+}
+
+struct ServerSettings {
+    upstream: SocketAddr,
+    allowed: Vec<PublicKey>,
+    config: ServerConfig,
+    timeouts: Timeouts,
+}
+
+impl ProxyServer {
+    /// Fails with an IO error of kind `InvalidInput` if the keypair can't be
+    /// converted to curve25519.
+    pub async fn bind<A: ToSocketAddrs>(
+        addr: A,
+        upstream: SocketAddr,
+        allowed: Vec<PublicKey>,
+        net_key: NetworkKey,
+        keypair: Keypair,
+        timeouts: Timeouts,
+    ) -> io::Result<ProxyServer> {
+        let config = ServerConfig::new(net_key, keypair).ok_or_else(invalid_key)?;
+        Ok(ProxyServer {
+            listener: TcpListener::bind(addr).await?,
+            settings: Arc::new(ServerSettings {
+                upstream,
+                allowed,
+                config,
+                timeouts,
+            }),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accept and forward connections, each in its own task. Never returns.
+    /// If accepting a connection fails (eg. when out of file descriptors),
+    /// tries again after a short delay.
+    pub async fn run(self) {
+        loop {
+            let (remote, _) = match self.listener.accept().await {
+                Ok(s) => s,
+                Err(_) => {
+                    Delay::new(ACCEPT_ERROR_DELAY).await;
+                    continue;
+                }
+            };
+            let settings = self.settings.clone();
+            ::tokio::spawn(async move {
+                let _ = forward_to_upstream(remote, &settings).await;
+            });
+        }
+    }
+}
+
+async fn forward_to_upstream(remote: TcpStream, settings: &ServerSettings) -> io::Result<()> {
+    let mut remote = remote.compat();
+    let options = ServerOptions::with_config(&settings.config)
+        .timeouts(settings.timeouts)
+        .authorize(|pk: &PublicKey| ready(settings.allowed.contains(pk)));
+    let outcome = crate::server_side_with(&mut remote, options)
+        .await
+        .map_err(handshake_error)?;
+
+    let upstream = TcpStream::connect(settings.upstream).await?;
+    pipe(upstream.compat(), remote, outcome.keys).await
+}
+
+/// Copy data both ways between the plaintext stream and the encrypted one,
+/// until both directions are closed.
+async fn pipe(
+    plain: Compat<TcpStream>,
+    encrypted: Compat<TcpStream>,
+    keys: HandshakeKeys,
+) -> io::Result<()> {
+    let (plain_r, plain_w) = plain.split();
+    let (box_r, box_w) = box_stream(encrypted, keys);
+    try_join(forward(plain_r, box_w), forward(box_r, plain_w)).await?;
+    Ok(())
+}
+
+/// Copy `r` to `w`, flushing after every read: the box-stream writer holds on
+/// to each box until it's flushed, and the peer may be waiting for a reply.
+async fn forward<R, W>(mut r: R, mut w: W) -> io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buf = vec![0; MAX_BOX_BODY_SIZE];
+    loop {
+        let n = r.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        w.write_all(&buf[..n]).await?;
+        w.flush().await?;
+    }
+    w.close().await
+}
+
+fn handshake_error(e: crate::HandshakeError<io::Error>) -> io::Error {
+    match e {
+        crate::HandshakeError::Io(e) => e,
+        e => io::Error::new(io::ErrorKind::PermissionDenied, e.to_string()),
+    }
+}
+
+fn invalid_key() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "key can't be converted to curve25519",
+    )
+}
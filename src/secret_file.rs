@@ -0,0 +1,198 @@
+//! Loading and creating the JSON `secret` file (usually `~/.ssb/secret`)
+//! that holds an SSB identity, in the format written by the JS stack:
+//!
+//! ```text
+//! # this is your SECRET name.
+//! # ...
+//! {
+//!   "curve": "ed25519",
+//!   "public": "<base64 public key>.ed25519",
+//!   "private": "<base64 private key>.ed25519",
+//!   "id": "@<base64 public key>.ed25519"
+//! }
+//! # ...
+//! ```
+//!
+//! Lines starting with `#` are comments.
+
+use crate::bytes::AsBytes;
+use crate::FeedId;
+
+use serde::{Deserialize, Serialize};
+use ssb_crypto::{Keypair, PublicKey};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum SecretFileError {
+    Io(io::Error),
+    /// The file (minus comments) isn't valid JSON with the expected fields.
+    Json(serde_json::Error),
+    UnsupportedCurve(String),
+    InvalidPublicKey,
+    InvalidPrivateKey,
+    /// The public key doesn't belong to the private key.
+    KeyMismatch,
+    /// The id doesn't match the public key.
+    IdMismatch,
+}
+
+impl From<io::Error> for SecretFileError {
+    fn from(e: io::Error) -> Self {
+        SecretFileError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for SecretFileError {
+    fn from(e: serde_json::Error) -> Self {
+        SecretFileError::Json(e)
+    }
+}
+
+impl fmt::Display for SecretFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use SecretFileError::*;
+        match self {
+            Io(e) => write!(f, "IO error: {}", e),
+            Json(e) => write!(f, "Corrupted secret file: {}", e),
+            UnsupportedCurve(c) => write!(f, "Unsupported curve in secret file: {}", c),
+            InvalidPublicKey => write!(f, "Invalid public key in secret file"),
+            InvalidPrivateKey => write!(f, "Invalid private key in secret file"),
+            KeyMismatch => write!(f, "Public key in secret file doesn't match the private key"),
+            IdMismatch => write!(f, "Id in secret file doesn't match the public key"),
+        }
+    }
+}
+
+impl std::error::Error for SecretFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SecretFileError::Io(e) => Some(e),
+            SecretFileError::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SecretFile {
+    curve: String,
+    public: String,
+    private: String,
+    id: String,
+}
+
+/// `$HOME/.ssb/secret`, if the `HOME` environment variable is set.
+pub fn default_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|h| Path::new(&h).join(".ssb").join("secret"))
+}
+
+/// Read and validate the secret file at the given path.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Keypair, SecretFileError> {
+    parse(&fs::read_to_string(path)?)
+}
+
+/// Parse and validate the contents of a secret file.
+pub fn parse(s: &str) -> Result<Keypair, SecretFileError> {
+    use SecretFileError::*;
+
+    let json: String = s
+        .lines()
+        .filter(|l| !l.trim_start().starts_with('#'))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let file: SecretFile = serde_json::from_str(&json)?;
+
+    if file.curve != "ed25519" {
+        return Err(UnsupportedCurve(file.curve));
+    }
+    let public = decode_key(&file.public, 32)
+        .and_then(|b| PublicKey::from_slice(&b))
+        .ok_or(InvalidPublicKey)?;
+
+    // The private key is the 32 byte seed, followed by the public key.
+    let private = decode_key(&file.private, 64).ok_or(InvalidPrivateKey)?;
+    let keypair = Keypair::from_seed(&private[..32]).ok_or(InvalidPrivateKey)?;
+    if keypair.public.0[..] != private[32..] || keypair.public != public {
+        return Err(KeyMismatch);
+    }
+
+    let id: FeedId = file.id.parse().map_err(|_| IdMismatch)?;
+    if id.0 != public {
+        return Err(IdMismatch);
+    }
+    Ok(keypair)
+}
+
+/// Decode a `<base64>.ed25519` key of the given length.
+fn decode_key(s: &str, len: usize) -> Option<Vec<u8>> {
+    let b = base64::decode(s.strip_suffix(".ed25519")?).ok()?;
+    if b.len() == len {
+        Some(b)
+    } else {
+        None
+    }
+}
+
+/// The contents of a secret file for the given keypair, with the usual comments.
+pub fn format(keypair: &Keypair) -> String {
+    let id = FeedId(keypair.public).to_string();
+    let file = SecretFile {
+        curve: "ed25519".to_string(),
+        public: format!("{}.ed25519", base64::encode(keypair.public.0)),
+        private: format!("{}.ed25519", base64::encode(keypair.as_bytes())),
+        id: id.clone(),
+    };
+    let json = serde_json::to_string_pretty(&file).unwrap();
+    format!(
+        "# this is your SECRET name.\n\
+         # this name gives you magical powers.\n\
+         # with it you can mark your messages so that your friends can verify\n\
+         # that they really did come from you.\n\
+         #\n\
+         # if any one learns this name, they can use it to destroy your identity\n\
+         # NEVER show this to anyone!!!\n\
+         \n\
+         {}\n\
+         \n\
+         # WARNING! It's vital that you DO NOT edit OR share your secret name\n\
+         # instead, share your public name\n\
+         # your public name: {}\n",
+        json, id
+    )
+}
+
+/// Generate a new keypair and write it to a new secret file at the given path,
+/// readable only by the owner. Creates the parent directory if needed.
+/// Fails if the file already exists.
+#[cfg(feature = "getrandom")]
+pub fn create<P: AsRef<Path>>(path: P) -> Result<Keypair, SecretFileError> {
+    use std::io::Write;
+
+    let path = path.as_ref();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let keypair = Keypair::generate();
+    let mut f = options.open(path)?;
+    f.write_all(format(&keypair).as_bytes())?;
+    f.sync_all()?;
+    Ok(keypair)
+}
+
+/// Load the secret file at the given path, or create it if it doesn't exist.
+#[cfg(feature = "getrandom")]
+pub fn load_or_create<P: AsRef<Path>>(path: P) -> Result<Keypair, SecretFileError> {
+    match load(&path) {
+        Err(SecretFileError::Io(e)) if e.kind() == io::ErrorKind::NotFound => create(path),
+        r => r,
+    }
+}
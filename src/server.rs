@@ -1,103 +1,67 @@
-use crate::bytes::{as_mut, as_ref};
-use crate::crypto::{keys::*, message::*, outcome::*, shared_secret::*};
+use crate::crypto::outcome::HandshakeOutcome;
 use crate::error::HandshakeError;
-use crate::util::send;
+use crate::handshake::ServerHandshake;
+use crate::options::{AuthorizeAsync, ServerOptions};
+use crate::util::{close_on_err, drive, drive_until, Limits};
 
-use core::mem::size_of;
 use futures_io::{AsyncRead, AsyncWrite};
-use futures_util::io::{AsyncReadExt, AsyncWriteExt};
-use ssb_crypto::{ephemeral::generate_ephemeral_keypair, Keypair, NetworkKey};
+#[cfg(feature = "getrandom")]
+use ssb_crypto::{Keypair, NetworkKey};
 use std::io;
 
 /// Perform the server side of the handshake using the given `AsyncRead + AsyncWrite` stream.
 /// Closes the stream on handshake failure.
+#[cfg(feature = "getrandom")]
 pub async fn server_side<S>(
-    mut stream: S,
+    stream: S,
     net_key: &NetworkKey,
     keypair: &Keypair,
-) -> Result<HandshakeKeys, HandshakeError<io::Error>>
+) -> Result<HandshakeOutcome, HandshakeError<io::Error>>
 where
     S: AsyncRead + AsyncWrite + Unpin,
 {
-    let r = try_server_side(&mut stream, net_key, keypair).await;
-    if r.is_err() {
-        stream.close().await.unwrap_or(());
-    }
-    r
+    server_side_with(stream, ServerOptions::new(net_key, keypair)).await
 }
 
-async fn try_server_side<S>(
+/// Perform the server side of the handshake, with the given `ServerOptions`
+/// (timeouts, authorization, multiple network keys or identities, etc).
+/// Closes the stream on handshake failure.
+///
+/// Without the `getrandom` feature, the options must include an ephemeral keypair
+/// or an rng.
+pub async fn server_side_with<S, A>(
     mut stream: S,
-    net_key: &NetworkKey,
-    keypair: &Keypair,
-) -> Result<HandshakeKeys, HandshakeError<io::Error>>
+    mut options: ServerOptions<'_, A>,
+) -> Result<HandshakeOutcome, HandshakeError<io::Error>>
 where
     S: AsyncRead + AsyncWrite + Unpin,
+    A: AuthorizeAsync,
 {
-    use HandshakeError::*;
-
-    let (eph_pk, eph_sk) = {
-        let (p, s) = generate_ephemeral_keypair();
-        (ServerEphPublicKey(p), ServerEphSecretKey(s))
-    };
-
-    // Receive and verify client hello
-    let client_eph_pk = {
-        let mut buf = [0; size_of::<ClientHello>()];
-        stream.read_exact(&mut buf).await?;
-        as_ref::<ClientHello>(&buf)
-            .verify(&net_key)
-            .ok_or(ClientHelloVerifyFailed)?
-    };
-
-    // Send server hello
-    send(&mut stream, ServerHello::new(&eph_pk, &net_key)).await?;
-
-    // Derive shared secrets
-    let shared_a = SharedA::server_side(&eph_sk, &client_eph_pk).ok_or(SharedAInvalid)?;
-    let shared_b = SharedB::server_side(&keypair, &client_eph_pk).ok_or(SharedBInvalid)?;
-
-    // Receive and verify client auth
-    let (client_sig, client_pk) = {
-        let mut buf = [0u8; 112];
-        stream.read_exact(&mut buf).await?;
-
-        as_mut::<ClientAuth>(&mut buf)
-            .verify(&keypair, &net_key, &shared_a, &shared_b)
-            .ok_or(ClientAuthVerifyFailed)?
-    };
-
-    // Derive shared secret
-    let shared_c = SharedC::server_side(&eph_sk, &client_pk).ok_or(SharedCInvalid)?;
+    let hs = options.handshake();
+    let r = try_server_side(&mut stream, hs, options).await;
+    close_on_err(&mut stream, r).await
+}
 
-    // Send server accept
-    send(
-        &mut stream,
-        ServerAccept::new(
-            &keypair,
-            &client_pk,
-            &net_key,
-            &client_sig,
-            &shared_a,
-            &shared_b,
-            &shared_c,
-        ),
-    )
+async fn try_server_side<S, A>(
+    mut stream: S,
+    mut hs: ServerHandshake<'_>,
+    options: ServerOptions<'_, A>,
+) -> Result<HandshakeOutcome, HandshakeError<io::Error>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    A: AuthorizeAsync,
+{
+    let limits = Limits::start(&options.timeouts);
+    drive_until(&mut stream, &mut hs, &limits, |hs| {
+        hs.client_public_key().is_some()
+    })
     .await?;
 
-    Ok(HandshakeKeys {
-        read_key: client_to_server_key(
-            &ServerPublicKey(keypair.public),
-            &net_key,
-            &shared_a,
-            &shared_b,
-            &shared_c,
-        ),
-        read_starting_nonce: starting_nonce(&net_key, &eph_pk.0),
-
-        write_key: server_to_client_key(&client_pk, &net_key, &shared_a, &shared_b, &shared_c),
-        write_starting_nonce: starting_nonce(&net_key, &client_eph_pk.0),
-
-        peer_key: client_pk.0,
-    })
+    if let Some(client_pk) = hs.client_public_key() {
+        if !options.authorize.authorize(&client_pk).await {
+            return Err(HandshakeError::PeerRejected);
+        }
+    }
+    drive(&mut stream, &mut hs, &limits).await?;
+    Ok(hs.into_outcome().unwrap())
 }
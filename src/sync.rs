@@ -2,7 +2,7 @@
 //! mostly for no_std environments.
 
 mod client;
-pub use client::client_side;
+pub use client::{client_side, client_side_with};
 mod server;
-pub use server::server_side;
+pub use server::{server_side, server_side_with};
 mod util;
@@ -1,11 +1,43 @@
-use crate::bytes::AsBytes;
-use genio::Write;
+use crate::error::HandshakeError;
+use crate::handshake::{Handshake, Step, MAX_MESSAGE_SIZE};
+use genio::{Read, Write};
 
-pub fn send<S, M, IoErr>(stream: &mut S, msg: M) -> Result<(), IoErr>
+/// Run a handshake state machine to completion over the given stream.
+pub fn drive<S, H, IoErr>(stream: &mut S, hs: &mut H) -> Result<(), HandshakeError<IoErr>>
 where
-    S: Write<WriteError = IoErr, FlushError = IoErr>,
-    M: AsBytes,
+    S: Read<ReadError = IoErr> + Write<WriteError = IoErr, FlushError = IoErr>,
+    H: Handshake,
 {
-    stream.write_all(msg.as_bytes())?;
-    stream.flush()
+    drive_until(stream, hs, |_| false)
+}
+
+/// Run a handshake state machine over the given stream,
+/// until it's done or `stop` returns true.
+pub fn drive_until<S, H, F, IoErr>(
+    stream: &mut S,
+    hs: &mut H,
+    stop: F,
+) -> Result<(), HandshakeError<IoErr>>
+where
+    S: Read<ReadError = IoErr> + Write<WriteError = IoErr, FlushError = IoErr>,
+    H: Handshake,
+    F: Fn(&H) -> bool,
+{
+    let mut buf = [0u8; MAX_MESSAGE_SIZE];
+    while !stop(hs) {
+        match hs.step() {
+            Step::Send(n) => {
+                hs.write_message(&mut buf[..n]);
+                stream.write_all(&buf[..n])?;
+                stream.flush()?;
+            }
+            Step::Recv(n) => {
+                stream.read_exact(&mut buf[..n])?;
+                hs.read_message(&buf[..n])
+                    .map_err(HandshakeError::with_io)?;
+            }
+            Step::Done => break,
+        }
+    }
+    Ok(())
 }
@@ -0,0 +1,158 @@
+//! Keys and helpers shared by this crate's tests, benchmarks and fuzz targets.
+//!
+//! Enabled by the `testing` feature. Not covered by semver.
+//!
+//! The keys here are fixed, so they're public to anyone reading this file:
+//! a handshake using them authenticates nobody and has no forward secrecy.
+//! Never use them outside of tests.
+
+use crate::{ClientHandshake, HandshakeOutcome, ServerHandshake, Step, MAX_MESSAGE_SIZE};
+
+use genio::{Read, Write};
+use rand::{rngs::StdRng, SeedableRng};
+use ssb_crypto::ephemeral::{generate_ephemeral_keypair_with_rng, EphPublicKey, EphSecretKey};
+use ssb_crypto::{Keypair, NetworkKey};
+
+/// The client's long-term keypair.
+pub fn client_keypair() -> Keypair {
+    Keypair::from_seed(&[1; 32]).unwrap()
+}
+
+/// The server's long-term keypair.
+pub fn server_keypair() -> Keypair {
+    Keypair::from_seed(&[2; 32]).unwrap()
+}
+
+/// An ephemeral keypair generated from `seed`.
+pub fn eph_keypair(seed: u64) -> (EphPublicKey, EphSecretKey) {
+    generate_ephemeral_keypair_with_rng(&mut StdRng::seed_from_u64(seed))
+}
+
+/// The client's ephemeral keypair.
+pub fn client_eph_keypair() -> (EphPublicKey, EphSecretKey) {
+    eph_keypair(1)
+}
+
+/// The server's ephemeral keypair.
+pub fn server_eph_keypair() -> (EphPublicKey, EphSecretKey) {
+    eph_keypair(2)
+}
+
+/// The main network key and both long-term keypairs.
+pub struct Keys {
+    pub net_key: NetworkKey,
+    pub client: Keypair,
+    pub server: Keypair,
+}
+
+impl Keys {
+    pub fn new() -> Keys {
+        Keys {
+            net_key: NetworkKey::SSB_MAIN_NET,
+            client: client_keypair(),
+            server: server_keypair(),
+        }
+    }
+
+    pub fn client(&self, eph_kp: (EphPublicKey, EphSecretKey)) -> ClientHandshake<'_> {
+        ClientHandshake::new(&self.net_key, &self.client, &self.server.public, eph_kp)
+    }
+
+    pub fn server(&self, eph_kp: (EphPublicKey, EphSecretKey)) -> ServerHandshake<'_> {
+        ServerHandshake::new(&self.net_key, &self.server, eph_kp)
+    }
+
+    /// A handshake between the fixed long-term and ephemeral keys.
+    pub fn exchange(&self) -> Exchange {
+        exchange(
+            self.client(client_eph_keypair()),
+            self.server(server_eph_keypair()),
+        )
+    }
+}
+
+impl Default for Keys {
+    fn default() -> Keys {
+        Keys::new()
+    }
+}
+
+/// The messages sent by each side of a completed handshake, and its outcomes.
+pub struct Exchange {
+    /// Client hello and client auth.
+    pub c2s: Vec<u8>,
+    /// Server hello and server accept.
+    pub s2c: Vec<u8>,
+    pub client: HandshakeOutcome,
+    pub server: HandshakeOutcome,
+}
+
+/// Run a handshake in memory.
+///
+/// # Panics
+/// If the handshake fails, or the two sides disagree about whose turn it is.
+pub fn exchange(mut client: ClientHandshake, mut server: ServerHandshake) -> Exchange {
+    let (mut c2s, mut s2c) = (Vec::new(), Vec::new());
+    let mut buf = [0u8; MAX_MESSAGE_SIZE];
+    loop {
+        match (client.step(), server.step()) {
+            (Step::Send(n), Step::Recv(m)) => {
+                assert_eq!(n, m);
+                client.write_message(&mut buf[..n]);
+                server.read_message(&buf[..n]).unwrap();
+                c2s.extend_from_slice(&buf[..n]);
+            }
+            (Step::Recv(n), Step::Send(m)) => {
+                assert_eq!(n, m);
+                server.write_message(&mut buf[..n]);
+                client.read_message(&buf[..n]).unwrap();
+                s2c.extend_from_slice(&buf[..n]);
+            }
+            (Step::Done, Step::Done) => break,
+            steps => panic!("mismatched steps: {:?}", steps),
+        }
+    }
+    Exchange {
+        c2s,
+        s2c,
+        client: client.into_outcome().unwrap(),
+        server: server.into_outcome().unwrap(),
+    }
+}
+
+/// Reads the peer's messages from a byte slice; discards everything written.
+pub struct ReplayStream<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> ReplayStream<'a> {
+    pub fn new(input: &'a [u8]) -> ReplayStream<'a> {
+        ReplayStream { input }
+    }
+}
+
+impl Read for ReplayStream<'_> {
+    type ReadError = ();
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        let n = buf.len().min(self.input.len());
+        buf[..n].copy_from_slice(&self.input[..n]);
+        self.input = &self.input[n..];
+        Ok(n)
+    }
+}
+
+impl Write for ReplayStream<'_> {
+    type WriteError = ();
+    type FlushError = ();
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, ()> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn size_hint(&mut self, _bytes: usize) {}
+}
@@ -0,0 +1,66 @@
+//! Handshake functions for streams implementing tokio's `AsyncRead + AsyncWrite`
+//! traits (eg. `tokio::net::TcpStream`), so no compat layer is needed.
+//!
+//! These behave exactly like the top-level functions of the same name;
+//! the stream is shut down on handshake failure.
+
+use crate::crypto::outcome::HandshakeOutcome;
+use crate::error::HandshakeError;
+use crate::options::{AuthorizeAsync, ClientOptions, ServerOptions};
+
+use ::tokio::io::{AsyncRead, AsyncWrite};
+use ssb_crypto::{Keypair, NetworkKey, PublicKey};
+use std::io;
+use tokio_util::compat::TokioAsyncReadCompatExt;
+
+/// Perform the client side of the handshake over a tokio `AsyncRead + AsyncWrite` stream.
+/// Shuts down the stream on handshake failure.
+pub async fn client_side<S>(
+    stream: S,
+    net_key: &NetworkKey,
+    keypair: &Keypair,
+    server_pk: &PublicKey,
+) -> Result<HandshakeOutcome, HandshakeError<io::Error>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    client_side_with(stream, ClientOptions::new(net_key, keypair, server_pk)).await
+}
+
+/// Perform the client side of the handshake, with the given `ClientOptions`.
+/// Shuts down the stream on handshake failure.
+pub async fn client_side_with<S>(
+    stream: S,
+    options: ClientOptions<'_>,
+) -> Result<HandshakeOutcome, HandshakeError<io::Error>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    crate::client_side_with(stream.compat(), options).await
+}
+
+/// Perform the server side of the handshake over a tokio `AsyncRead + AsyncWrite` stream.
+/// Shuts down the stream on handshake failure.
+pub async fn server_side<S>(
+    stream: S,
+    net_key: &NetworkKey,
+    keypair: &Keypair,
+) -> Result<HandshakeOutcome, HandshakeError<io::Error>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    server_side_with(stream, ServerOptions::new(net_key, keypair)).await
+}
+
+/// Perform the server side of the handshake, with the given `ServerOptions`.
+/// Shuts down the stream on handshake failure.
+pub async fn server_side_with<S, A>(
+    stream: S,
+    options: ServerOptions<'_, A>,
+) -> Result<HandshakeOutcome, HandshakeError<io::Error>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    A: AuthorizeAsync,
+{
+    crate::server_side_with(stream.compat(), options).await
+}
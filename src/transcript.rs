@@ -0,0 +1,403 @@
+//! Recording of handshake transcripts, and offline replay of a recorded
+//! handshake to find out exactly which step failed.
+//!
+//! Wrap the stream in a [`TranscriptRecorder`] before calling `client_side`
+//! or `server_side`. If the handshake fails, save the [`Transcript`] (its
+//! `Display` output can be parsed back with `str::parse`), and later feed it
+//! to [`replay_client`] or [`replay_server`], along with the same keys that
+//! were used for the handshake.
+//! The ephemeral keypair is needed too, so the handshake must have been
+//! performed with an ephemeral keypair set in its `ClientOptions` or `ServerOptions`,
+//! and a copy of it kept for the replay.
+//!
+//! Replay is a testing and debugging tool. Anyone holding the transcript and the
+//! ephemeral secret key can recompute that handshake's session keys, so
+//! keeping the ephemeral secret gives up forward secrecy for the connection.
+//! Only do so for diagnostic connections, and never derive ephemeral keys from
+//! a fixed seed outside of tests.
+
+use crate::crypto::outcome::HandshakeKeys;
+use crate::error::HandshakeError;
+use crate::handshake::{
+    ClientHandshake, Handshake, Role, ServerHandshake, Stage, Step, MAX_MESSAGE_SIZE,
+};
+
+use core::cmp::min;
+use core::convert::Infallible;
+use core::fmt;
+use core::mem::take;
+use core::pin::Pin;
+use core::str::FromStr;
+use core::task::{Context, Poll};
+use futures_io::{AsyncRead, AsyncWrite};
+use ssb_crypto::ephemeral::{EphPublicKey, EphSecretKey};
+use ssb_crypto::{Keypair, NetworkKey, PublicKey};
+use std::io;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// A single handshake message, as seen on the wire.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Message {
+    pub stage: Stage,
+    pub direction: Direction,
+    /// Time between the start of the recording and the last byte of the message.
+    pub elapsed: Duration,
+    /// Shorter than `stage.message_size()` if the message was cut off.
+    pub bytes: Vec<u8>,
+}
+
+/// The messages of a (possibly incomplete) handshake, from one side's point of view.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Transcript {
+    pub role: Role,
+    pub started: SystemTime,
+    pub messages: Vec<Message>,
+}
+
+impl Transcript {
+    fn message(&self, stage: Stage) -> Option<&Message> {
+        self.messages.iter().find(|m| m.stage == stage)
+    }
+}
+
+/// Wraps a stream, and records the handshake messages that pass through it.
+/// Anything sent or received after the handshake is not recorded.
+pub struct TranscriptRecorder<S> {
+    inner: S,
+    started: Instant,
+    transcript: Transcript,
+    sent: Vec<u8>,
+    received: Vec<u8>,
+}
+
+impl<S> TranscriptRecorder<S> {
+    pub fn new(inner: S, role: Role) -> TranscriptRecorder<S> {
+        TranscriptRecorder {
+            inner,
+            started: Instant::now(),
+            transcript: Transcript {
+                role,
+                started: SystemTime::now(),
+                messages: Vec::new(),
+            },
+            sent: Vec::new(),
+            received: Vec::new(),
+        }
+    }
+
+    /// The messages recorded so far, including any partially sent or received message.
+    pub fn transcript(&self) -> Transcript {
+        let mut t = self.transcript.clone();
+        for &direction in &[Direction::Sent, Direction::Received] {
+            let partial = match direction {
+                Direction::Sent => &self.sent,
+                Direction::Received => &self.received,
+            };
+            if let (Some(stage), false) = (self.next_stage(direction), partial.is_empty()) {
+                t.messages.push(Message {
+                    stage,
+                    direction,
+                    elapsed: self.started.elapsed(),
+                    bytes: partial.clone(),
+                });
+            }
+        }
+        t
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn next_stage(&self, direction: Direction) -> Option<Stage> {
+        let sender = match (direction, self.transcript.role) {
+            (Direction::Sent, role) => role,
+            (Direction::Received, Role::Client) => Role::Server,
+            (Direction::Received, Role::Server) => Role::Client,
+        };
+        Stage::ALL
+            .iter()
+            .copied()
+            .filter(|s| s.sender() == sender)
+            .find(|s| self.transcript.message(*s).is_none())
+    }
+
+    fn record(&mut self, direction: Direction, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            let stage = match self.next_stage(direction) {
+                Some(s) => s,
+                None => return,
+            };
+            let partial = match direction {
+                Direction::Sent => &mut self.sent,
+                Direction::Received => &mut self.received,
+            };
+            let n = min(bytes.len(), stage.message_size() - partial.len());
+            partial.extend_from_slice(&bytes[..n]);
+            bytes = &bytes[n..];
+
+            if partial.len() == stage.message_size() {
+                let bytes = take(partial);
+                self.transcript.messages.push(Message {
+                    stage,
+                    direction,
+                    elapsed: self.started.elapsed(),
+                    bytes,
+                });
+            }
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for TranscriptRecorder<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let r = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(n)) = r {
+            this.record(Direction::Received, &buf[..n]);
+        }
+        r
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for TranscriptRecorder<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let r = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = r {
+            this.record(Direction::Sent, &buf[..n]);
+        }
+        r
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_close(cx)
+    }
+}
+
+#[derive(Debug)]
+pub enum ReplayFailure {
+    /// The message isn't in the transcript; the handshake stopped before it.
+    Missing,
+    /// With the given keys, we would have sent a different message than the recorded one.
+    /// This most likely means that the wrong keys were provided for the replay.
+    SentMismatch,
+    /// The received message failed to verify.
+    Failed(HandshakeError<Infallible>),
+}
+
+/// The handshake step that failed during a replay, and why.
+#[derive(Debug)]
+pub struct ReplayError {
+    pub stage: Stage,
+    pub failure: ReplayFailure,
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.failure {
+            ReplayFailure::Missing => write!(f, "{}: message missing from transcript", self.stage),
+            ReplayFailure::SentMismatch => write!(
+                f,
+                "{}: recorded message differs from the replayed one (wrong keys?)",
+                self.stage
+            ),
+            ReplayFailure::Failed(e) => write!(f, "{}: {}", self.stage, e),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+/// Re-run the client side of a recorded handshake.
+pub fn replay_client(
+    transcript: &Transcript,
+    net_key: &NetworkKey,
+    keypair: &Keypair,
+    server_pk: &PublicKey,
+    eph_kp: (EphPublicKey, EphSecretKey),
+) -> Result<HandshakeKeys, ReplayError> {
+    let mut hs = ClientHandshake::new(net_key, keypair, server_pk, eph_kp);
+    replay(transcript, &mut hs)?;
+    Ok(hs.into_keys().unwrap())
+}
+
+/// Re-run the server side of a recorded handshake.
+pub fn replay_server(
+    transcript: &Transcript,
+    net_key: &NetworkKey,
+    keypair: &Keypair,
+    eph_kp: (EphPublicKey, EphSecretKey),
+) -> Result<HandshakeKeys, ReplayError> {
+    let mut hs = ServerHandshake::new(net_key, keypair, eph_kp);
+    replay(transcript, &mut hs)?;
+    Ok(hs.into_keys().unwrap())
+}
+
+fn replay<H: Handshake>(transcript: &Transcript, hs: &mut H) -> Result<(), ReplayError> {
+    let mut buf = [0u8; MAX_MESSAGE_SIZE];
+    while let Some(stage) = hs.stage() {
+        let err = |failure| ReplayError { stage, failure };
+        let recorded = transcript.message(stage);
+
+        match hs.step() {
+            Step::Send(n) => {
+                hs.write_message(&mut buf[..n]);
+                match recorded {
+                    Some(m) if m.bytes[..] == buf[..n] => {}
+                    Some(_) => return Err(err(ReplayFailure::SentMismatch)),
+                    None => return Err(err(ReplayFailure::Missing)),
+                }
+            }
+            Step::Recv(_) => {
+                let m = recorded.ok_or_else(|| err(ReplayFailure::Missing))?;
+                hs.read_message(&m.bytes)
+                    .map_err(|e| err(ReplayFailure::Failed(e)))?;
+            }
+            Step::Done => break,
+        }
+    }
+    Ok(())
+}
+
+// Text format, one item per line:
+//   role client
+//   started <milliseconds since unix epoch>
+//   <elapsed milliseconds> <sent|received> <stage> <hex bytes>
+
+impl fmt::Display for Transcript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let role = match self.role {
+            Role::Client => "client",
+            Role::Server => "server",
+        };
+        let started = self
+            .started
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        writeln!(f, "role {}", role)?;
+        writeln!(f, "started {}", started)?;
+
+        for m in &self.messages {
+            let direction = match m.direction {
+                Direction::Sent => "sent",
+                Direction::Received => "received",
+            };
+            write!(
+                f,
+                "{} {} {} ",
+                m.elapsed.as_millis(),
+                direction,
+                stage_name(m.stage)
+            )?;
+            for b in &m.bytes {
+                write!(f, "{:02x}", b)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+fn stage_name(stage: Stage) -> &'static str {
+    match stage {
+        Stage::ClientHello => "client_hello",
+        Stage::ServerHello => "server_hello",
+        Stage::ClientAuth => "client_auth",
+        Stage::ServerAccept => "server_accept",
+    }
+}
+
+/// Error returned when parsing a `Transcript` fails; holds the (1-based) line number.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseTranscriptError {
+    pub line: usize,
+}
+
+impl fmt::Display for ParseTranscriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid transcript at line {}", self.line)
+    }
+}
+
+impl std::error::Error for ParseTranscriptError {}
+
+impl FromStr for Transcript {
+    type Err = ParseTranscriptError;
+
+    fn from_str(s: &str) -> Result<Transcript, ParseTranscriptError> {
+        let mut role = None;
+        let mut started = None;
+        let mut messages = Vec::new();
+
+        for (i, line) in s.lines().enumerate() {
+            let err = ParseTranscriptError { line: i + 1 };
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words[..] {
+                [] => {}
+                ["role", "client"] => role = Some(Role::Client),
+                ["role", "server"] => role = Some(Role::Server),
+                ["started", ms] => {
+                    let ms = ms.parse().map_err(|_| err)?;
+                    started = Some(UNIX_EPOCH + Duration::from_millis(ms));
+                }
+                [elapsed, direction, stage, hex] => {
+                    let direction = match direction {
+                        "sent" => Direction::Sent,
+                        "received" => Direction::Received,
+                        _ => return Err(err),
+                    };
+                    let stage = *Stage::ALL
+                        .iter()
+                        .find(|s| stage_name(**s) == stage)
+                        .ok_or(err)?;
+                    messages.push(Message {
+                        stage,
+                        direction,
+                        elapsed: Duration::from_millis(elapsed.parse().map_err(|_| err)?),
+                        bytes: decode_hex(hex).ok_or(err)?,
+                    });
+                }
+                _ => return Err(err),
+            }
+        }
+
+        let end = ParseTranscriptError {
+            line: s.lines().count(),
+        };
+        Ok(Transcript {
+            role: role.ok_or(end)?,
+            started: started.unwrap_or(UNIX_EPOCH),
+            messages,
+        })
+    }
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 == 1 {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| s.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+        .collect()
+}
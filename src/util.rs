@@ -1,12 +1,149 @@
-use crate::bytes::AsBytes;
-use futures_io::AsyncWrite;
-use futures_util::AsyncWriteExt;
+use crate::error::HandshakeError;
+use crate::handshake::{Handshake, Stage, Step, MAX_MESSAGE_SIZE};
+use core::future::Future;
+use futures_io::{AsyncRead, AsyncWrite};
+use futures_timer::Delay;
+use futures_util::future::{select, Either};
+use futures_util::io::{AsyncReadExt, AsyncWriteExt};
+use futures_util::pin_mut;
+use std::io;
+use std::time::{Duration, Instant};
 
-pub async fn send<S, M>(stream: &mut S, msg: M) -> Result<(), futures_io::Error>
+/// Time limits for the async handshake functions.
+///
+/// Timeouts don't depend on any particular async runtime.
+/// If a limit is hit, the handshake fails with `HandshakeError::Timeout`,
+/// which names the message that was being sent or received at the time.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Timeouts {
+    /// Limit on the duration of the whole handshake.
+    pub handshake: Option<Duration>,
+    /// Limit on the time spent sending or receiving any single message.
+    pub message: Option<Duration>,
+}
+
+impl Timeouts {
+    /// No time limits; the handshake may wait forever on a stalled peer.
+    pub const NONE: Timeouts = Timeouts {
+        handshake: None,
+        message: None,
+    };
+}
+
+/// `Timeouts`, with the handshake deadline fixed at the time the handshake started.
+pub struct Limits {
+    deadline: Option<Instant>,
+    message: Option<Duration>,
+}
+
+impl Limits {
+    pub fn start(timeouts: &Timeouts) -> Limits {
+        Limits {
+            deadline: timeouts.handshake.map(|d| Instant::now() + d),
+            message: timeouts.message,
+        }
+    }
+
+    /// Time limit for the next message.
+    fn next(&self) -> Option<Duration> {
+        match self.deadline {
+            Some(dl) => {
+                let left = dl.saturating_duration_since(Instant::now());
+                Some(self.message.map_or(left, |m| m.min(left)))
+            }
+            None => self.message,
+        }
+    }
+}
+
+/// How long `close_on_err` waits for the stream to close.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Close the stream if the handshake failed.
+///
+/// A peer that stopped reading could stall the close forever, so it's given
+/// `CLOSE_TIMEOUT`, after which the stream is left as it is.
+pub async fn close_on_err<S, T>(
+    stream: &mut S,
+    r: Result<T, HandshakeError<io::Error>>,
+) -> Result<T, HandshakeError<io::Error>>
 where
     S: AsyncWrite + Unpin,
-    M: AsBytes,
 {
-    stream.write_all(msg.as_bytes()).await?;
-    stream.flush().await
+    if r.is_err() {
+        let close = stream.close();
+        pin_mut!(close);
+        let _ = select(close, Delay::new(CLOSE_TIMEOUT)).await;
+    }
+    r
+}
+
+/// Run a handshake state machine to completion over the given stream.
+pub async fn drive<S, H>(
+    stream: &mut S,
+    hs: &mut H,
+    limits: &Limits,
+) -> Result<(), HandshakeError<io::Error>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    H: Handshake,
+{
+    drive_until(stream, hs, limits, |_| false).await
+}
+
+/// Run a handshake state machine over the given stream,
+/// until it's done or `stop` returns true.
+pub async fn drive_until<S, H, F>(
+    stream: &mut S,
+    hs: &mut H,
+    limits: &Limits,
+    stop: F,
+) -> Result<(), HandshakeError<io::Error>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    H: Handshake,
+    F: Fn(&H) -> bool,
+{
+    let mut buf = [0u8; MAX_MESSAGE_SIZE];
+    while !stop(hs) {
+        let stage = hs.stage();
+        match hs.step() {
+            Step::Send(n) => {
+                hs.write_message(&mut buf[..n]);
+                let msg = &buf[..n];
+                with_timeout(limits.next(), stage, async {
+                    stream.write_all(msg).await?;
+                    stream.flush().await
+                })
+                .await?;
+            }
+            Step::Recv(n) => {
+                with_timeout(limits.next(), stage, stream.read_exact(&mut buf[..n])).await?;
+                hs.read_message(&buf[..n])
+                    .map_err(HandshakeError::with_io)?;
+            }
+            Step::Done => break,
+        }
+    }
+    Ok(())
+}
+
+async fn with_timeout<F, T>(
+    limit: Option<Duration>,
+    stage: Option<Stage>,
+    f: F,
+) -> Result<T, HandshakeError<io::Error>>
+where
+    F: Future<Output = io::Result<T>>,
+{
+    match (limit, stage) {
+        (Some(d), Some(stage)) => {
+            pin_mut!(f);
+            match select(f, Delay::new(d)).await {
+                Either::Left((r, _)) => Ok(r?),
+                Either::Right(_) => Err(HandshakeError::Timeout { stage }),
+            }
+        }
+        _ => Ok(f.await?),
+    }
 }